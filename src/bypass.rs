@@ -0,0 +1,83 @@
+use kube::core::admission::AdmissionRequest;
+use kube::core::DynamicObject;
+
+use crate::config::BreakGlassConfig;
+
+/// Comma-separated policy names to bypass, e.g. `image_registry,labels`.
+pub const BYPASS_ANNOTATION: &str = "sentinel.io/bypass";
+/// Free-text justification, required for a bypass to be honored.
+pub const REASON_ANNOTATION: &str = "sentinel.io/bypass-reason";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BypassOutcome {
+    Bypassed,
+    Unauthorized,
+    MissingReason,
+}
+
+impl BypassOutcome {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            BypassOutcome::Bypassed => "bypassed",
+            BypassOutcome::Unauthorized => "unauthorized",
+            BypassOutcome::MissingReason => "missing_reason",
+        }
+    }
+}
+
+/// A break-glass bypass requested through object annotations.
+pub struct BypassRequest {
+    policies: Vec<String>,
+    pub reason: Option<String>,
+    pub user: String,
+    authorized: bool,
+}
+
+impl BypassRequest {
+    /// Reads the bypass annotations from the request's object, if present.
+    ///
+    /// Only the requesting user is checked. Pods a controller creates from an
+    /// annotated pod template are requested by the controller's service
+    /// account, so the bypass is not honored for them.
+    pub fn from_request(
+        config: &BreakGlassConfig,
+        request: &AdmissionRequest<DynamicObject>,
+    ) -> Option<Self> {
+        let annotations = request.object.as_ref()?.metadata.annotations.as_ref()?;
+        let policies: Vec<String> = annotations
+            .get(BYPASS_ANNOTATION)?
+            .split(',')
+            .map(|p| p.trim().to_string())
+            .filter(|p| !p.is_empty())
+            .collect();
+
+        let reason = annotations
+            .get(REASON_ANNOTATION)
+            .map(|r| r.trim().to_string())
+            .filter(|r| !r.is_empty());
+
+        let groups = request.user_info.groups.as_deref().unwrap_or_default();
+        let authorized = groups.iter().any(|g| config.groups.contains(g));
+
+        Some(Self {
+            policies,
+            reason,
+            user: request.user_info.username.clone().unwrap_or_default(),
+            authorized,
+        })
+    }
+
+    pub fn covers(&self, policy: &str) -> bool {
+        self.policies.iter().any(|p| p == policy)
+    }
+
+    pub fn outcome(&self) -> BypassOutcome {
+        if !self.authorized {
+            BypassOutcome::Unauthorized
+        } else if self.reason.is_none() {
+            BypassOutcome::MissingReason
+        } else {
+            BypassOutcome::Bypassed
+        }
+    }
+}
@@ -0,0 +1,226 @@
+//! Approved `image:tag` to digest mappings, read from a local file.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::sync::{Arc, Mutex, PoisonError, RwLock, Weak};
+use std::time::Duration;
+
+use thiserror::Error;
+use tokio::runtime::Handle;
+use tracing::{error, info, warn};
+
+use crate::policies::image_registry::{parse_image_ref, ImageRef};
+use crate::reload::config_hash;
+
+#[derive(Debug, Error)]
+pub enum CatalogError {
+    #[error("failed to read digest catalog: {0}")]
+    Read(#[from] std::io::Error),
+    #[error("failed to parse digest catalog: {0}")]
+    Parse(#[from] serde_yaml::Error),
+    #[error("digest catalog entry '{image}' has invalid digest '{digest}'")]
+    InvalidDigest { image: String, digest: String },
+    #[error("digest catalog entry '{0}' must be an image with a tag")]
+    InvalidImage(String),
+}
+
+/// A catalog file, re-read when its contents change.
+///
+/// The file is a YAML (or JSON) map of `image:tag` to `sha256:<hex>`. Images
+/// are normalized like the image policy does, so `nginx:1.25` and
+/// `docker.io/library/nginx:1.25` are the same entry. Like the config file,
+/// the catalog is polled by content hash from a background task; a broken
+/// file is logged and the previous entries stay in use. Lookups only read
+/// the entries loaded last.
+pub struct DigestCatalog {
+    path: String,
+    state: RwLock<CatalogState>,
+}
+
+#[derive(Default)]
+struct CatalogState {
+    hash: String,
+    digests: HashMap<String, String>,
+}
+
+/// Catalogs in use, so that every policy naming the same file (global,
+/// overrides, and the engine being replaced by a reload) shares one copy and
+/// one poller.
+static CATALOGS: Mutex<Vec<(CatalogKey, Weak<DigestCatalog>)>> = Mutex::new(Vec::new());
+
+type CatalogKey = (String, Duration);
+
+impl DigestCatalog {
+    /// The catalog for `path`, loading it and starting its poller on first
+    /// use. The poller checks the file every `interval` (never if zero) and
+    /// stops once the catalog is no longer used.
+    pub fn shared(path: &str, interval: Duration) -> Arc<Self> {
+        let mut catalogs = CATALOGS.lock().unwrap_or_else(PoisonError::into_inner);
+        catalogs.retain(|(_, catalog)| catalog.strong_count() > 0);
+        let key = (path.to_string(), interval);
+        if let Some(catalog) = catalogs
+            .iter()
+            .find(|(k, _)| *k == key)
+            .and_then(|(_, catalog)| catalog.upgrade())
+        {
+            return catalog;
+        }
+
+        let catalog = Arc::new(Self {
+            path: path.to_string(),
+            state: RwLock::default(),
+        });
+        catalog.apply(fs::read(path));
+        if !interval.is_zero() {
+            match Handle::try_current() {
+                Ok(handle) => {
+                    handle.spawn(poll(Arc::downgrade(&catalog), interval));
+                }
+                Err(_) => warn!(path, "no async runtime, digest catalog will not be reloaded"),
+            }
+        }
+        catalogs.push((key, Arc::downgrade(&catalog)));
+        catalog
+    }
+
+    /// The digest for `repository:tag`.
+    pub fn digest(&self, repository: &str, tag: &str) -> Option<String> {
+        let state = self.state.read().unwrap_or_else(PoisonError::into_inner);
+        state.digests.get(&format!("{repository}:{tag}")).cloned()
+    }
+
+    /// Swaps in the entries of a freshly read file, if its contents changed.
+    fn apply(&self, contents: std::io::Result<Vec<u8>>) {
+        let contents = match contents {
+            Ok(contents) => contents,
+            Err(e) => {
+                error!(path = %self.path, "{}", CatalogError::from(e));
+                return;
+            }
+        };
+        let hash = config_hash(&contents);
+        if hash == self.state.read().unwrap_or_else(PoisonError::into_inner).hash {
+            return;
+        }
+
+        let parsed = parse_catalog(&contents);
+        let mut state = self.state.write().unwrap_or_else(PoisonError::into_inner);
+        // Remember broken files too, so they are reported once.
+        state.hash = hash;
+        match parsed {
+            Ok(digests) => {
+                info!(path = %self.path, entries = digests.len(), "digest catalog loaded");
+                state.digests = digests;
+            }
+            Err(e) => error!(path = %self.path, "keeping previous digest catalog: {e}"),
+        }
+    }
+}
+
+async fn poll(catalog: Weak<DigestCatalog>, interval: Duration) {
+    let mut ticker = tokio::time::interval(interval);
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    // The first tick completes immediately, and the file was just loaded.
+    ticker.tick().await;
+    loop {
+        ticker.tick().await;
+        let Some(path) = catalog.upgrade().map(|c| c.path.clone()) else {
+            return;
+        };
+        let contents = tokio::fs::read(&path).await;
+        match catalog.upgrade() {
+            Some(catalog) => catalog.apply(contents),
+            None => return,
+        }
+    }
+}
+
+fn parse_catalog(contents: &[u8]) -> Result<HashMap<String, String>, CatalogError> {
+    let entries: BTreeMap<String, String> = serde_yaml::from_slice(contents)?;
+    entries
+        .into_iter()
+        .map(|(image, digest)| {
+            let ImageRef {
+                repository,
+                tag,
+                has_digest,
+                ..
+            } = parse_image_ref(&image);
+            if tag.is_empty() || has_digest {
+                return Err(CatalogError::InvalidImage(image));
+            }
+            if !is_sha256_digest(&digest) {
+                return Err(CatalogError::InvalidDigest { image, digest });
+            }
+            Ok((format!("{repository}:{tag}"), digest))
+        })
+        .collect()
+}
+
+fn is_sha256_digest(digest: &str) -> bool {
+    digest.strip_prefix("sha256:").is_some_and(|hex| {
+        hex.len() == 64 && hex.bytes().all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DIGEST: &str = "sha256:0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef";
+
+    fn temp_catalog(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("sentinel-catalog-{name}-{}.yaml", std::process::id()))
+    }
+
+    #[test]
+    fn test_reload_on_change() {
+        let path = temp_catalog("reload");
+        let path_str = path.to_str().unwrap();
+        fs::write(&path, format!("nginx:1.25: {DIGEST}\n")).unwrap();
+
+        let catalog = DigestCatalog::shared(path_str, Duration::ZERO);
+        assert_eq!(catalog.digest("docker.io/library/nginx", "1.25").as_deref(), Some(DIGEST));
+        assert_eq!(catalog.digest("docker.io/library/nginx", "1.26"), None);
+        assert!(Arc::ptr_eq(&catalog, &DigestCatalog::shared(path_str, Duration::ZERO)));
+
+        fs::write(&path, format!("docker.io/library/nginx:1.26: {DIGEST}\n")).unwrap();
+        catalog.apply(fs::read(&path));
+        assert_eq!(catalog.digest("docker.io/library/nginx", "1.25"), None);
+        assert!(catalog.digest("docker.io/library/nginx", "1.26").is_some());
+
+        // A broken file keeps the previous entries.
+        fs::write(&path, "nginx:1.27: sha256:nothex\n").unwrap();
+        catalog.apply(fs::read(&path));
+        assert!(catalog.digest("docker.io/library/nginx", "1.26").is_some());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_background_poll() {
+        let path = temp_catalog("poll");
+        let path_str = path.to_str().unwrap();
+        fs::write(&path, format!("nginx:1.25: {DIGEST}\n")).unwrap();
+
+        let catalog = DigestCatalog::shared(path_str, Duration::from_millis(10));
+        fs::write(&path, format!("nginx:1.26: {DIGEST}\n")).unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(catalog.digest("docker.io/library/nginx", "1.26").is_some());
+
+        drop(catalog);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_parse_catalog() {
+        assert!(matches!(
+            parse_catalog(format!("nginx: {DIGEST}").as_bytes()),
+            Err(CatalogError::InvalidImage(_))
+        ));
+        assert!(matches!(
+            parse_catalog(b"nginx:1.25: sha256:abc"),
+            Err(CatalogError::InvalidDigest { .. })
+        ));
+    }
+}
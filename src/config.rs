@@ -1,45 +1,112 @@
-use std::fmt;
+use std::collections::BTreeMap;
 
 use figment::{Figment, providers::{Env, Format, Yaml}};
+use kube::core::admission::Operation;
 use serde::{Deserialize, Serialize};
+use thiserror::Error;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+use crate::quantity::Quantity;
+use crate::signature::KeyError;
+
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("override '{name}' references unknown policy '{policy}'")]
+    UnknownPolicy { name: String, policy: String },
+    #[error("override '{name}' produces an invalid config: {source}")]
+    InvalidOverride {
+        name: String,
+        source: serde_json::Error,
+    },
+    #[error("pod template for kind '{kind}' has an invalid JSON pointer: {source}")]
+    InvalidPodTemplate {
+        kind: String,
+        source: json_patch::jsonptr::ParseError,
+    },
+    #[error("{field} has an invalid regex '{pattern}': {source}")]
+    InvalidRegex {
+        field: String,
+        pattern: String,
+        source: regex::Error,
+    },
+    #[error("image_signature public key {index} is invalid: {source}")]
+    InvalidPublicKey { index: usize, source: KeyError },
+    #[error("image_signature is enabled without any public_keys")]
+    NoPublicKeys,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub enum PolicyMode {
     Enforce,
     Warn,
+    /// Violations only show up in metrics and logs; mutations are skipped.
+    Audit,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
-pub enum PolicyName {
-    ResourceLimits,
-    ImageRegistry,
-    Labels,
-    TopologySpread,
-}
-
-impl PolicyName {
-    pub const ALL: [PolicyName; 4] = [
-        PolicyName::ResourceLimits,
-        PolicyName::ImageRegistry,
-        PolicyName::Labels,
-        PolicyName::TopologySpread,
-    ];
-
+impl PolicyMode {
     pub fn as_str(self) -> &'static str {
         match self {
-            PolicyName::ResourceLimits => "resource_limits",
-            PolicyName::ImageRegistry => "image_registry",
-            PolicyName::Labels => "labels",
-            PolicyName::TopologySpread => "topology_spread",
+            PolicyMode::Enforce => "enforce",
+            PolicyMode::Warn => "warn",
+            PolicyMode::Audit => "audit",
         }
     }
 }
 
-impl fmt::Display for PolicyName {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        f.write_str(self.as_str())
-    }
+/// Settings shared by every policy config, regardless of policy-specific fields.
+pub trait PolicyConfig {
+    fn enabled(&self) -> bool;
+    fn mode(&self) -> &PolicyMode;
+    fn exclude(&self) -> &ExcludeRules;
+    /// On UPDATE, only deny violations the old object did not already have;
+    /// pre-existing ones become warnings. Applies to enforce mode.
+    fn ratchet(&self) -> bool;
+    /// Admission operations the policy runs for.
+    fn operations(&self) -> &[Operation];
+}
+
+/// Requests matching any of these criteria skip the policy entirely.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ExcludeRules {
+    /// Namespace globs, e.g. `kube-*`.
+    #[serde(default)]
+    pub namespaces: Vec<String>,
+    /// Selector on the object's own labels. An empty selector matches nothing.
+    #[serde(default)]
+    pub object_labels: LabelSelector,
+    /// Exact usernames from `userInfo.username`.
+    #[serde(default)]
+    pub users: Vec<String>,
+    /// Exact group names from `userInfo.groups`.
+    #[serde(default)]
+    pub groups: Vec<String>,
+    /// `namespace/name` globs matched against service account usernames.
+    #[serde(default)]
+    pub service_accounts: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LabelSelector {
+    #[serde(default)]
+    pub match_labels: BTreeMap<String, String>,
+    #[serde(default)]
+    pub match_expressions: Vec<LabelSelectorRequirement>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LabelSelectorRequirement {
+    pub key: String,
+    pub operator: SelectorOperator,
+    #[serde(default)]
+    pub values: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SelectorOperator {
+    In,
+    NotIn,
+    Exists,
+    DoesNotExist,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -68,6 +135,58 @@ fn default_log_level() -> String {
     "info".to_string()
 }
 
+fn default_config_reload_interval_secs() -> u64 {
+    10
+}
+
+fn default_catalog_reload_interval_secs() -> u64 {
+    30
+}
+
+fn default_verify_timeout_ms() -> u64 {
+    3000
+}
+
+fn default_verify_cache_ttl_secs() -> u64 {
+    300
+}
+
+fn default_operations() -> Vec<Operation> {
+    vec![Operation::Create, Operation::Update]
+}
+
+fn default_any() -> String {
+    "*".to_string()
+}
+
+pub(crate) fn default_pod_templates() -> Vec<PodTemplateKind> {
+    let kind = |group: &str, kind: &str, template: &str| PodTemplateKind {
+        group: group.to_string(),
+        version: default_any(),
+        kind: kind.to_string(),
+        template: template.to_string(),
+        labels: None,
+    };
+    vec![
+        kind("", "Pod", ""),
+        kind("", "ReplicationController", "/spec/template"),
+        kind("", "PodTemplate", "/template"),
+        kind("apps", "Deployment", "/spec/template"),
+        kind("apps", "ReplicaSet", "/spec/template"),
+        kind("apps", "StatefulSet", "/spec/template"),
+        kind("apps", "DaemonSet", "/spec/template"),
+        kind("batch", "Job", "/spec/template"),
+        kind("batch", "CronJob", "/spec/jobTemplate/spec/template"),
+        kind("argoproj.io", "Rollout", "/spec/template"),
+    ]
+}
+
+fn default_scalable_kinds() -> Vec<String> {
+    ["Deployment", "StatefulSet", "ReplicaSet", "ReplicationController", "Rollout"]
+        .map(String::from)
+        .to_vec()
+}
+
 fn default_cpu_request() -> String {
     "100m".to_string()
 }
@@ -108,6 +227,10 @@ pub struct SentinelConfig {
     pub metrics_addr: String,
     #[serde(default = "default_log_level")]
     pub log_level: String,
+    /// How often to check the config file for changes; 0 disables hot reload.
+    /// Only `policies` are reloaded, other settings need a restart.
+    #[serde(default = "default_config_reload_interval_secs")]
+    pub config_reload_interval_secs: u64,
     pub policies: PoliciesConfig,
 }
 
@@ -120,16 +243,94 @@ pub struct PoliciesConfig {
     #[serde(rename = "required_labels")]
     pub labels: RequiredLabelsPolicy,
     pub topology_spread: TopologySpreadPolicy,
+    #[serde(default)]
+    pub replicas: ReplicasPolicy,
+    #[serde(default)]
+    pub image_signature: ImageSignaturePolicy,
+    /// Checked in order; the first override with a namespace glob matching
+    /// the request's namespace replaces the global policy settings for it.
+    #[serde(default)]
+    pub overrides: Vec<PolicyOverride>,
+    #[serde(default)]
+    pub break_glass: BreakGlassConfig,
+    /// Kinds that carry a pod template, checked in order. Objects of other
+    /// kinds are ignored by pod-level policies.
+    #[serde(default = "default_pod_templates")]
+    pub pod_templates: Vec<PodTemplateKind>,
+}
+
+/// Where objects of one kind keep their pod template.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PodTemplateKind {
+    /// Globs matched against the request's group, version and kind.
+    #[serde(default)]
+    pub group: String,
+    #[serde(default = "default_any")]
+    pub version: String,
+    pub kind: String,
+    /// JSON pointer to the `PodTemplateSpec`; empty for the object itself (Pod).
+    pub template: String,
+    /// JSON pointer to the pod labels; defaults to `<template>/metadata/labels`.
+    #[serde(default)]
+    pub labels: Option<String>,
+}
+
+/// Who may bypass policies with the `sentinel.io/bypass` annotation.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BreakGlassConfig {
+    /// Groups from `userInfo.groups` allowed to bypass; empty disables break-glass.
+    #[serde(default)]
+    pub groups: Vec<String>,
+}
+
+/// Per-namespace replacement of policy fields. Keys under `policies` use the
+/// same section names as the global config (`enforce_resource_limits`, ...),
+/// and each field given replaces the global value.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PolicyOverride {
+    pub name: String,
+    /// Namespace name globs this override applies to (not a label selector).
+    pub namespaces: Vec<String>,
+    #[serde(default)]
+    pub policies: serde_json::Map<String, serde_json::Value>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ResourceLimitsPolicy {
     pub enabled: bool,
     pub mode: PolicyMode,
+    #[serde(default)]
+    pub exclude: ExcludeRules,
+    #[serde(default)]
+    pub ratchet: bool,
+    #[serde(default = "default_operations")]
+    pub operations: Vec<Operation>,
+    /// Per-container caps as quantities (`2500m`, `1.5Gi`). They take
+    /// precedence over the numeric `max_cpu_millicores`/`max_memory_mb`.
+    pub max_cpu: Option<Quantity>,
+    pub max_memory: Option<Quantity>,
     pub max_cpu_millicores: Option<u64>,
     pub max_memory_mb: Option<u64>,
+    /// Caps on the pod's effective requests and limits: all containers, the
+    /// init container peak and `spec.overhead`.
+    pub max_pod_cpu: Option<Quantity>,
+    pub max_pod_memory: Option<Quantity>,
+    pub max_pod_cpu_millicores: Option<u64>,
+    pub max_pod_memory_mb: Option<u64>,
+    /// Maximum limit/request ratio per resource (`cpu`, `memory`).
+    #[serde(default)]
+    pub max_limit_request_ratio: BTreeMap<String, f64>,
+    /// Per-resource rules keyed by resource name (`ephemeral-storage`,
+    /// `hugepages-2Mi`, `nvidia.com/gpu`, ...). Entries for `cpu` and
+    /// `memory` take precedence over the fields above and below.
+    #[serde(default)]
+    pub resources: BTreeMap<String, ResourceRule>,
     #[serde(default)]
     pub inject_defaults: bool,
+    /// LimitRange-style defaults for matching containers, checked in order;
+    /// the first match overrides the per-resource defaults.
+    #[serde(default)]
+    pub default_profiles: Vec<DefaultProfile>,
     #[serde(default = "default_cpu_request")]
     pub default_cpu_request: String,
     #[serde(default = "default_cpu_limit")]
@@ -140,26 +341,156 @@ pub struct ResourceLimitsPolicy {
     pub default_memory_limit: String,
 }
 
+/// Limits and defaults for one container resource, as Kubernetes quantities.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ResourceRule {
+    /// Cap on the container's request and limit.
+    pub max: Option<Quantity>,
+    /// Floor on the container's request.
+    pub min_request: Option<Quantity>,
+    /// Injected when `inject_defaults` is set and the request is missing.
+    pub default_request: Option<String>,
+    /// Injected when `inject_defaults` is set and the limit is missing.
+    pub default_limit: Option<String>,
+    /// Containers must set a limit, and a request unless the limit stands in
+    /// for it (Kubernetes copies a limit into a missing request).
+    #[serde(default)]
+    pub required: bool,
+}
+
+impl ResourceRule {
+    pub fn default_for(&self, section: &str) -> Option<&str> {
+        match section {
+            "requests" => self.default_request.as_deref(),
+            _ => self.default_limit.as_deref(),
+        }
+    }
+}
+
+/// Injected defaults for containers matching every criterion given.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DefaultProfile {
+    pub name: String,
+    /// Namespace globs.
+    #[serde(default)]
+    pub namespaces: Vec<String>,
+    /// Regex matched against the container name.
+    pub container_name: Option<String>,
+    /// Glob matched against the full image reference.
+    pub image: Option<String>,
+    /// Resource name to quantity.
+    #[serde(default)]
+    pub default_request: BTreeMap<String, String>,
+    #[serde(default)]
+    pub default_limit: BTreeMap<String, String>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AllowedRegistriesPolicy {
     pub enabled: bool,
     pub mode: PolicyMode,
+    #[serde(default)]
+    pub exclude: ExcludeRules,
+    #[serde(default)]
+    pub ratchet: bool,
+    #[serde(default = "default_operations")]
+    pub operations: Vec<Operation>,
+    /// Allowed registries: path prefixes (`gcr.io/myproject`), globs matched
+    /// segment by segment (`*.dkr.ecr.*.amazonaws.com/team-*`), or `regex:`
+    /// patterns that must match the whole registry path.
     pub registries: Vec<String>,
+    /// Registries allowed in matching namespaces on top of `registries`.
+    #[serde(default)]
+    pub namespace_registries: Vec<NamespaceRegistries>,
     #[serde(default)]
     pub allow_latest_tag: bool,
+    /// Images must be pinned by an `@sha256:` digest.
+    #[serde(default)]
+    pub require_digest: bool,
+    /// Namespace globs `require_digest` is limited to; empty means all.
+    #[serde(default)]
+    pub require_digest_namespaces: Vec<String>,
+    /// Tag regexes; when set, tags must match at least one.
+    #[serde(default)]
+    pub allowed_tags: Vec<String>,
+    /// Tag regexes; tags matching any are rejected.
+    #[serde(default)]
+    pub denied_tags: Vec<String>,
+    /// Image rewrites applied on the mutate path; the first matching rule wins.
+    #[serde(default)]
+    pub mirrors: Vec<MirrorRule>,
+    /// Pins tags to digests from a local catalog on the mutate path.
+    pub digest_catalog: Option<DigestCatalogConfig>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DigestCatalogConfig {
+    /// YAML or JSON map of `image:tag` to `sha256:<hex>` digests.
+    pub path: String,
+    /// Seconds between background checks of the file for changes; 0
+    /// disables them.
+    #[serde(default = "default_catalog_reload_interval_secs")]
+    pub reload_interval_secs: u64,
+    /// Deny images whose tag is not in the catalog.
+    #[serde(default)]
+    pub deny_unknown_tags: bool,
+}
+
+/// Rewrites images under the `from` repository prefix to `to`, e.g.
+/// `docker.io/library` to `mirror.corp/dockerhub/library`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MirrorRule {
+    /// Matched segment by segment against the normalized repository
+    /// (`nginx` is `docker.io/library/nginx`), like `registries` entries.
+    pub from: String,
+    pub to: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NamespaceRegistries {
+    /// Namespace globs.
+    pub namespaces: Vec<String>,
+    pub registries: Vec<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RequiredLabelsPolicy {
     pub enabled: bool,
     pub mode: PolicyMode,
+    #[serde(default)]
+    pub exclude: ExcludeRules,
+    #[serde(default)]
+    pub ratchet: bool,
+    #[serde(default = "default_operations")]
+    pub operations: Vec<Operation>,
     pub labels: Vec<RequiredLabel>,
+    #[serde(default)]
+    pub targets: LabelTargets,
+}
+
+/// Which label sets [`RequiredLabelsPolicy`] checks.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LabelTargets {
+    /// The object's own `metadata.labels`.
+    #[default]
+    Object,
+    /// The pod template labels, plus any template they are nested in (such
+    /// as a CronJob's job template).
+    PodTemplate,
+    Both,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TopologySpreadPolicy {
     pub enabled: bool,
     pub mode: PolicyMode,
+    #[serde(default)]
+    pub exclude: ExcludeRules,
+    #[serde(default)]
+    pub ratchet: bool,
+    #[serde(default = "default_operations")]
+    pub operations: Vec<Operation>,
     #[serde(default = "default_max_skew")]
     pub max_skew: i32,
     #[serde(default = "default_topology_key")]
@@ -170,23 +501,196 @@ pub struct TopologySpreadPolicy {
     pub inject_if_missing: bool,
 }
 
-impl PoliciesConfig {
-    pub fn policy_mode(&self, name: PolicyName) -> &PolicyMode {
-        match name {
-            PolicyName::ResourceLimits => &self.resource_limits.mode,
-            PolicyName::ImageRegistry => &self.image_registry.mode,
-            PolicyName::Labels => &self.labels.mode,
-            PolicyName::TopologySpread => &self.topology_spread.mode,
+/// Replica count bounds for scalable workloads, their `scale` subresource
+/// and the HorizontalPodAutoscalers targeting them.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReplicasPolicy {
+    pub enabled: bool,
+    pub mode: PolicyMode,
+    #[serde(default)]
+    pub exclude: ExcludeRules,
+    #[serde(default)]
+    pub ratchet: bool,
+    #[serde(default = "default_operations")]
+    pub operations: Vec<Operation>,
+    /// Kinds with `spec.replicas`. Scale subresource requests are checked
+    /// for the built-in ones (Deployment, StatefulSet, ReplicaSet,
+    /// ReplicationController and Argo Rollout), matched by API group and
+    /// resource; other kinds only on the object itself.
+    #[serde(default = "default_scalable_kinds")]
+    pub kinds: Vec<String>,
+    pub min: Option<u32>,
+    pub max: Option<u32>,
+    /// Checked in order; the first rule matching the workload's kind and
+    /// namespace replaces `min` and `max`.
+    #[serde(default)]
+    pub rules: Vec<ReplicaRule>,
+}
+
+impl Default for ReplicasPolicy {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            mode: PolicyMode::Enforce,
+            exclude: ExcludeRules::default(),
+            ratchet: false,
+            operations: default_operations(),
+            kinds: default_scalable_kinds(),
+            min: None,
+            max: None,
+            rules: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReplicaRule {
+    pub name: String,
+    /// Kind globs; empty matches every kind.
+    #[serde(default)]
+    pub kinds: Vec<String>,
+    /// Namespace globs; empty matches every namespace.
+    #[serde(default)]
+    pub namespaces: Vec<String>,
+    pub min: Option<u32>,
+    pub max: Option<u32>,
+}
+
+/// Cosign-style signature checks for images, fetched from their registry.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImageSignaturePolicy {
+    pub enabled: bool,
+    pub mode: PolicyMode,
+    #[serde(default)]
+    pub exclude: ExcludeRules,
+    #[serde(default)]
+    pub ratchet: bool,
+    #[serde(default = "default_operations")]
+    pub operations: Vec<Operation>,
+    /// PEM `PUBLIC KEY`s (ECDSA P-256, as made by `cosign generate-key-pair`);
+    /// a signature from any of them is accepted.
+    #[serde(default)]
+    pub public_keys: Vec<String>,
+    /// Repositories whose images must be signed, matched like
+    /// `allowed_registries` entries; empty means every image.
+    #[serde(default)]
+    pub images: Vec<String>,
+    /// Time allowed for checking all of a request's images, which are looked
+    /// up concurrently; keep it below the webhook's `timeoutSeconds`.
+    #[serde(default = "default_verify_timeout_ms")]
+    pub timeout_ms: u64,
+    /// Whether registry errors and timeouts deny (`fail`) or allow (`ignore`).
+    #[serde(default)]
+    pub failure_policy: FailurePolicy,
+    /// How long a digest's verification result is reused.
+    #[serde(default = "default_verify_cache_ttl_secs")]
+    pub cache_ttl_secs: u64,
+    /// Registry hosts reached over plain HTTP, e.g. `localhost:5000`.
+    #[serde(default)]
+    pub insecure_registries: Vec<String>,
+}
+
+impl Default for ImageSignaturePolicy {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            mode: PolicyMode::Enforce,
+            exclude: ExcludeRules::default(),
+            ratchet: false,
+            operations: default_operations(),
+            public_keys: Vec::new(),
+            images: Vec::new(),
+            timeout_ms: default_verify_timeout_ms(),
+            failure_policy: FailurePolicy::default(),
+            cache_ttl_secs: default_verify_cache_ttl_secs(),
+            insecure_registries: Vec::new(),
         }
     }
+}
+
+/// Named after the webhook `failurePolicy` values.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FailurePolicy {
+    /// Fail closed: deny images that could not be verified.
+    #[default]
+    Fail,
+    /// Fail open: allow them, with a warning in the logs.
+    Ignore,
+}
+
+macro_rules! impl_policy_config {
+    ($($ty:ty),* $(,)?) => {
+        $(
+            impl PolicyConfig for $ty {
+                fn enabled(&self) -> bool {
+                    self.enabled
+                }
+
+                fn mode(&self) -> &PolicyMode {
+                    &self.mode
+                }
 
-    pub fn policy_enabled(&self, name: PolicyName) -> bool {
-        match name {
-            PolicyName::ResourceLimits => self.resource_limits.enabled,
-            PolicyName::ImageRegistry => self.image_registry.enabled,
-            PolicyName::Labels => self.labels.enabled,
-            PolicyName::TopologySpread => self.topology_spread.enabled,
+                fn exclude(&self) -> &ExcludeRules {
+                    &self.exclude
+                }
+
+                fn ratchet(&self) -> bool {
+                    self.ratchet
+                }
+
+                fn operations(&self) -> &[Operation] {
+                    &self.operations
+                }
+            }
+        )*
+    };
+}
+
+impl_policy_config!(
+    ResourceLimitsPolicy,
+    AllowedRegistriesPolicy,
+    RequiredLabelsPolicy,
+    TopologySpreadPolicy,
+    ReplicasPolicy,
+    ImageSignaturePolicy,
+);
+
+impl PoliciesConfig {
+    /// Returns the effective config for requests matched by `over`.
+    pub fn with_override(&self, over: &PolicyOverride) -> Result<PoliciesConfig, ConfigError> {
+        let invalid = |source| ConfigError::InvalidOverride {
+            name: over.name.clone(),
+            source,
+        };
+
+        let mut base = serde_json::to_value(self).map_err(invalid)?;
+        let sections = base
+            .as_object_mut()
+            .expect("PoliciesConfig serializes to an object");
+        sections.remove("overrides");
+        sections.remove("break_glass");
+        sections.remove("pod_templates");
+
+        for (policy, fields) in &over.policies {
+            let section = sections
+                .get_mut(policy)
+                .ok_or_else(|| ConfigError::UnknownPolicy {
+                    name: over.name.clone(),
+                    policy: policy.clone(),
+                })?;
+            match (section.as_object_mut(), fields.as_object()) {
+                (Some(section), Some(fields)) => {
+                    for (key, value) in fields {
+                        section.insert(key.clone(), value.clone());
+                    }
+                }
+                // Not a map of fields; let deserialization report the type error.
+                _ => *section = fields.clone(),
+            }
         }
+
+        serde_json::from_value(base).map_err(invalid)
     }
 }
 
@@ -199,3 +703,79 @@ impl SentinelConfig {
             .map_err(Box::new)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POLICIES: &str = r#"
+enforce_resource_limits:
+  enabled: true
+  mode: enforce
+  max_cpu_millicores: 4000
+  max_memory_mb: 8192
+allowed_registries:
+  enabled: true
+  mode: enforce
+  registries: ["gcr.io/myproject"]
+required_labels:
+  enabled: true
+  mode: enforce
+  labels:
+    - key: "app.kubernetes.io/name"
+topology_spread:
+  enabled: false
+  mode: warn
+overrides:
+  - name: platform
+    namespaces: ["platform-*"]
+    policies:
+      enforce_resource_limits:
+        mode: warn
+        max_cpu_millicores: 8000
+      allowed_registries:
+        registries: ["registry.corp"]
+  - name: typo
+    namespaces: ["sandbox"]
+    policies:
+      resource_limits:
+        mode: warn
+"#;
+
+    #[test]
+    fn test_with_override() {
+        let config: PoliciesConfig = Figment::from(Yaml::string(POLICIES)).extract().unwrap();
+
+        let effective = config.with_override(&config.overrides[0]).unwrap();
+        assert_eq!(effective.resource_limits.mode, PolicyMode::Warn);
+        assert_eq!(effective.resource_limits.max_cpu_millicores, Some(8000));
+        assert_eq!(effective.resource_limits.max_memory_mb, Some(8192));
+        assert_eq!(effective.image_registry.registries, vec!["registry.corp"]);
+        assert_eq!(effective.labels.labels.len(), 1);
+        assert!(effective.overrides.is_empty());
+
+        assert!(matches!(
+            config.with_override(&config.overrides[1]),
+            Err(ConfigError::UnknownPolicy { .. })
+        ));
+    }
+
+    #[test]
+    fn test_invalid_quantity_rejected_at_load() {
+        let invalid = POLICIES.replace("max_memory_mb: 8192", "max_memory: 1.5GB");
+        let result: Result<PoliciesConfig, _> = Figment::from(Yaml::string(&invalid)).extract();
+        assert!(result.unwrap_err().to_string().contains("'1.5GB' is not a valid quantity"));
+
+        let config: PoliciesConfig = Figment::from(Yaml::string(POLICIES)).extract().unwrap();
+        let over: PolicyOverride = serde_json::from_value(serde_json::json!({
+            "name": "bad",
+            "namespaces": ["*"],
+            "policies": {"enforce_resource_limits": {"max_pod_cpu": "lots"}},
+        }))
+        .unwrap();
+        assert!(matches!(
+            config.with_override(&over),
+            Err(ConfigError::InvalidOverride { .. })
+        ));
+    }
+}
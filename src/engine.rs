@@ -1,121 +1,674 @@
+use std::cell::OnceCell;
+use std::sync::Arc;
 use std::time::{Duration, Instant};
 
 use json_patch::PatchOperation;
-use kube::core::admission::AdmissionRequest;
+use kube::core::admission::{AdmissionRequest, Operation};
 use kube::core::DynamicObject;
+use tracing::{debug, info, warn};
 
-use crate::config::{PoliciesConfig, PolicyMode, PolicyName};
-use crate::policies::labels::CompiledLabel;
-use crate::policies::{self, PolicyOutput};
+use crate::bypass::{BypassOutcome, BypassRequest};
+use crate::config::{BreakGlassConfig, ConfigError, PoliciesConfig, PolicyMode};
+use crate::policies::{self, ContainerList, PodTemplates, Policy, PolicyOutput, Violation};
+use crate::selector::glob_match;
 
+/// Outcome of one policy for one request. The policy's mode decides which
+/// list its violations land in.
 pub struct PolicyResult {
-    pub policy_name: PolicyName,
-    pub allowed: bool,
-    pub message: Option<String>,
-    pub warnings: Vec<String>,
+    pub policy_name: &'static str,
+    pub mode: PolicyMode,
+    pub exempted: bool,
+    /// Violations that deny the request.
+    pub denials: Vec<Violation>,
+    /// Violations returned to the client as warnings.
+    pub warnings: Vec<Violation>,
+    /// Violations found in audit mode, only logged and counted.
+    pub audit_violations: Vec<Violation>,
     pub patches: Vec<PatchOperation>,
+    /// Set when a break-glass bypass annotation named this policy and it had
+    /// denials to bypass.
+    pub bypass: Option<BypassOutcome>,
     pub duration: Duration,
 }
 
+impl PolicyResult {
+    fn new(policy: &dyn Policy, duration: Duration) -> Self {
+        Self {
+            policy_name: policy.name(),
+            mode: *policy.config().mode(),
+            exempted: false,
+            denials: vec![],
+            warnings: vec![],
+            audit_violations: vec![],
+            patches: vec![],
+            bypass: None,
+            duration,
+        }
+    }
+
+    pub fn allowed(&self) -> bool {
+        self.denials.is_empty()
+    }
+}
+
 pub struct PolicyEngine {
-    pub config: PoliciesConfig,
-    compiled_labels: Vec<CompiledLabel>,
+    policies: Vec<Arc<dyn Policy>>,
+    overrides: Vec<NamespaceOverride>,
+    break_glass: BreakGlassConfig,
+}
+
+/// Policies built from the global config with one override applied. Only
+/// the policies whose sections the override changes are rebuilt; the others
+/// are shared with the global set.
+struct NamespaceOverride {
+    name: String,
+    namespaces: Vec<String>,
+    policies: Vec<Arc<dyn Policy>>,
 }
 
 impl PolicyEngine {
-    pub fn new(config: PoliciesConfig) -> Self {
-        let compiled_labels = policies::labels::compile_labels(&config.labels);
-        Self {
-            config,
-            compiled_labels,
-        }
+    pub fn new(config: PoliciesConfig) -> Result<Self, ConfigError> {
+        let templates = PodTemplates::new(&config.pod_templates)?;
+        let global = policies::registry(&config, &templates)?;
+        let overrides = config
+            .overrides
+            .iter()
+            .map(|over| {
+                let effective = config.with_override(over)?;
+                let sections: Vec<&String> = over.policies.keys().collect();
+                Ok(NamespaceOverride {
+                    name: over.name.clone(),
+                    namespaces: over.namespaces.clone(),
+                    policies: policies::rebuild(&global, &sections, &effective, &templates)?,
+                })
+            })
+            .collect::<Result<_, ConfigError>>()?;
+
+        Ok(Self {
+            policies: global,
+            overrides,
+            break_glass: config.break_glass,
+        })
+    }
+
+    /// All registered policies, including disabled ones.
+    pub fn policies(&self) -> &[Arc<dyn Policy>] {
+        &self.policies
     }
 
     pub fn evaluate_validate(
         &self,
         request: &AdmissionRequest<DynamicObject>,
     ) -> Vec<PolicyResult> {
-        self.evaluate_all(request, false)
+        self.evaluate_all(request, false, None)
     }
 
     pub fn evaluate_mutate(
         &self,
         request: &AdmissionRequest<DynamicObject>,
     ) -> Vec<PolicyResult> {
-        self.evaluate_all(request, true)
+        self.evaluate_all(request, true, None)
+    }
+
+    /// Validates with the `policy` policy alone, for webhooks registered for
+    /// resources only that policy handles, such as scale subresources.
+    pub fn evaluate_policy(
+        &self,
+        request: &AdmissionRequest<DynamicObject>,
+        policy: &str,
+    ) -> Vec<PolicyResult> {
+        self.evaluate_all(request, false, Some(policy))
+    }
+
+    /// Picks the policy set for the request's namespace: the first matching
+    /// override, or the global policies.
+    fn resolve(&self, request: &AdmissionRequest<DynamicObject>) -> &[Arc<dyn Policy>] {
+        let Some(ns) = request.namespace.as_deref() else {
+            return &self.policies;
+        };
+
+        match self
+            .overrides
+            .iter()
+            .find(|over| over.namespaces.iter().any(|pattern| glob_match(pattern, ns)))
+        {
+            Some(over) => {
+                info!(
+                    uid = %request.uid,
+                    namespace = ns,
+                    override_name = %over.name,
+                    "namespace override applied"
+                );
+                &over.policies
+            }
+            None => &self.policies,
+        }
     }
 
     fn evaluate_all(
         &self,
         request: &AdmissionRequest<DynamicObject>,
         include_patches: bool,
+        only: Option<&str>,
     ) -> Vec<PolicyResult> {
-        PolicyName::ALL
+        let previous = OnceCell::new();
+        let bypass = BypassRequest::from_request(&self.break_glass, request);
+
+        self.resolve(request)
             .iter()
-            .filter(|name| self.config.policy_enabled(**name))
-            .map(|&name| {
+            .filter(|policy| {
+                policy.config().enabled()
+                    && policy.config().operations().contains(&request.operation)
+                    && only.is_none_or(|name| policy.name() == name)
+            })
+            .map(|policy| {
                 let start = Instant::now();
-                let output = match name {
-                    PolicyName::ResourceLimits => policies::resource_limits::evaluate(
-                        &self.config.resource_limits,
-                        request,
-                        include_patches,
-                    ),
-                    PolicyName::ImageRegistry => {
-                        policies::image_registry::evaluate(&self.config.image_registry, request)
-                    }
-                    PolicyName::Labels => {
-                        policies::labels::evaluate(&self.compiled_labels, request)
-                    }
-                    PolicyName::TopologySpread => policies::topology_spread::evaluate(
-                        &self.config.topology_spread,
-                        request,
-                        include_patches,
-                    ),
+                if let Some(reason) = policy.config().exclude().matches(request) {
+                    debug!(
+                        uid = %request.uid,
+                        policy = policy.name(),
+                        reason,
+                        "policy exempted by exclude rules"
+                    );
+                    return PolicyResult {
+                        exempted: true,
+                        ..PolicyResult::new(policy.as_ref(), start.elapsed())
+                    };
+                }
+
+                // Audit mode must not change objects, so it never mutates and
+                // reports everything a patch would otherwise have fixed.
+                let mutating = include_patches && *policy.config().mode() != PolicyMode::Audit;
+                let mut output = PolicyOutput {
+                    violations: policy.evaluate(request, mutating),
+                    preexisting: vec![],
+                    patches: if mutating {
+                        policy.mutate(request)
+                    } else {
+                        vec![]
+                    },
                 };
-                self.to_result(name, output, include_patches, start.elapsed())
+
+                let ratchet = policy.config().ratchet()
+                    && *policy.config().mode() == PolicyMode::Enforce
+                    && !output.violations.is_empty();
+                if ratchet {
+                    if let Some(previous) = previous.get_or_init(|| previous_request(request)) {
+                        let old = policy.evaluate(previous, mutating);
+                        let old: Vec<_> = old.iter().map(ratchet_key).collect();
+                        let (preexisting, introduced) = output
+                            .violations
+                            .into_iter()
+                            .partition(|v| old.contains(&ratchet_key(v)));
+                        output.violations = introduced;
+                        output.preexisting = preexisting;
+                    }
+                }
+
+                let mut result = self.to_result(policy.as_ref(), request, output, start.elapsed());
+                if let Some(bypass) = bypass.as_ref().filter(|b| b.covers(policy.name())) {
+                    apply_bypass(&mut result, bypass, request);
+                }
+                result
             })
             .collect()
     }
 
     fn to_result(
         &self,
-        name: PolicyName,
+        policy: &dyn Policy,
+        request: &AdmissionRequest<DynamicObject>,
         output: PolicyOutput,
-        include_patches: bool,
         duration: Duration,
     ) -> PolicyResult {
-        let patches = if include_patches {
-            output.patches
-        } else {
-            vec![]
+        let mut result = PolicyResult {
+            patches: output.patches,
+            ..PolicyResult::new(policy, duration)
         };
 
-        match self.config.policy_mode(name) {
-            PolicyMode::Enforce => PolicyResult {
-                policy_name: name,
-                allowed: output.violations.is_empty(),
-                message: if output.violations.is_empty() {
-                    None
-                } else {
-                    Some(output.violations.join("; "))
-                },
-                warnings: vec![],
-                patches,
-                duration,
-            },
-            PolicyMode::Warn => PolicyResult {
-                policy_name: name,
-                allowed: true,
-                message: None,
-                warnings: output
-                    .violations
+        match result.mode {
+            PolicyMode::Enforce => {
+                result.denials = output.violations;
+                result.warnings = output
+                    .preexisting
                     .into_iter()
-                    .map(|v| format!("{name}: {v}"))
-                    .collect(),
-                patches,
-                duration,
-            },
+                    .map(|mut v| {
+                        v.message = format!("pre-existing, not enforced on update: {}", v.message);
+                        v
+                    })
+                    .collect();
+            }
+            PolicyMode::Warn => result.warnings = output.violations,
+            PolicyMode::Audit => {
+                for violation in &output.violations {
+                    info!(
+                        uid = %request.uid,
+                        policy = violation.policy,
+                        rule = violation.rule,
+                        path = %violation.path,
+                        container = violation.container.as_deref(),
+                        kind = %request.kind.kind,
+                        namespace = request.namespace.as_deref().unwrap_or_default(),
+                        name = %request.name,
+                        message = %violation.message,
+                        "audit policy violation"
+                    );
+                }
+                result.audit_violations = output.violations;
+            }
         }
+
+        result
+    }
+}
+
+/// Turns the result's denials into warnings if the break-glass bypass is
+/// authorized and justified; otherwise the denials stand.
+fn apply_bypass(
+    result: &mut PolicyResult,
+    bypass: &BypassRequest,
+    request: &AdmissionRequest<DynamicObject>,
+) {
+    if result.denials.is_empty() {
+        return;
+    }
+
+    let outcome = bypass.outcome();
+    result.bypass = Some(outcome);
+    if outcome != BypassOutcome::Bypassed {
+        warn!(
+            uid = %request.uid,
+            policy = result.policy_name,
+            user = %bypass.user,
+            outcome = outcome.as_str(),
+            "break-glass bypass rejected"
+        );
+        return;
+    }
+
+    let reason = bypass.reason.as_deref().unwrap_or_default();
+    let rules: Vec<&str> = result.denials.iter().map(|v| v.rule).collect();
+    warn!(
+        uid = %request.uid,
+        policy = result.policy_name,
+        user = %bypass.user,
+        reason,
+        rules = ?rules,
+        kind = %request.kind.kind,
+        namespace = request.namespace.as_deref().unwrap_or_default(),
+        name = %request.name,
+        "policy bypassed by break-glass"
+    );
+    for mut violation in std::mem::take(&mut result.denials) {
+        violation.message = format!("bypassed by {} ({reason}): {}", bypass.user, violation.message);
+        result.warnings.push(violation);
+    }
+}
+
+/// What a violation is matched on when ratcheting. Container violations use
+/// the container name and the path below the container rather than its list
+/// index, so inserting or reordering containers keeps unchanged violations
+/// pre-existing.
+fn ratchet_key(violation: &Violation) -> (&str, &str, Option<&str>, String, &str) {
+    let path = match &violation.container {
+        Some(_) => container_relative_path(&violation.path),
+        None => violation.path.clone(),
+    };
+    (
+        violation.policy,
+        violation.rule,
+        violation.container.as_deref(),
+        path,
+        &violation.message,
+    )
+}
+
+/// `path` with everything up to and including the container index dropped,
+/// keeping the list name, e.g. `/spec/containers/2/image` becomes
+/// `containers/image`.
+fn container_relative_path(path: &str) -> String {
+    let tokens: Vec<&str> = path.split('/').collect();
+    let list = tokens.windows(2).position(|pair| {
+        ContainerList::ALL.iter().any(|list| list.field() == pair[0])
+            && pair[1].parse::<usize>().is_ok()
+    });
+    match list {
+        Some(i) => [&tokens[i..=i], &tokens[i + 2..]].concat().join("/"),
+        None => path.to_string(),
+    }
+}
+
+/// The request with `old_object` in place of `object`, so policies can be
+/// evaluated against the state before an UPDATE.
+fn previous_request(
+    request: &AdmissionRequest<DynamicObject>,
+) -> Option<AdmissionRequest<DynamicObject>> {
+    if !matches!(request.operation, Operation::Update) {
+        return None;
+    }
+    let old_object = request.old_object.clone()?;
+
+    let mut previous = request.clone();
+    previous.object = Some(old_object);
+    previous.old_object = None;
+    Some(previous)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::SentinelConfig;
+    use crate::policies::replicas;
+    use crate::testing::{pod, request};
+    use serde_json::{json, Value};
+
+    const POLICIES: &str = r#"
+enforce_resource_limits:
+  enabled: true
+  mode: audit
+  max_cpu_millicores: 1000
+  inject_defaults: true
+allowed_registries:
+  enabled: true
+  mode: warn
+  registries: ["gcr.io/myproject"]
+required_labels:
+  enabled: true
+  mode: enforce
+  labels:
+    - key: "app"
+topology_spread:
+  enabled: false
+  mode: enforce
+"#;
+
+    fn engine(yaml: &str) -> PolicyEngine {
+        PolicyEngine::new(serde_yaml::from_str(yaml).unwrap()).unwrap()
+    }
+
+    fn result<'a>(results: &'a [PolicyResult], name: &str) -> &'a PolicyResult {
+        results.iter().find(|r| r.policy_name == name).unwrap()
+    }
+
+    #[test]
+    fn test_modes() {
+        let engine = engine(POLICIES);
+        let req = request(
+            pod(json!({}), json!([{"name": "app", "image": "nginx:1.25"}])),
+            json!({}),
+        );
+        let results = engine.evaluate_mutate(&req);
+
+        let limits = result(&results, "resource_limits");
+        assert!(limits.allowed());
+        assert!(limits.warnings.is_empty());
+        assert_eq!(limits.audit_violations.len(), 1);
+        assert!(limits.patches.is_empty());
+
+        let registry = result(&results, "image_registry");
+        assert!(registry.allowed());
+        assert_eq!(registry.warnings.len(), 1);
+        assert_eq!(registry.warnings[0].rule, "registry_not_allowed");
+        assert_eq!(registry.warnings[0].path, "/spec/containers/0/image");
+        assert_eq!(registry.warnings[0].container.as_deref(), Some("app"));
+
+        let labels = result(&results, "labels");
+        assert!(!labels.allowed());
+        assert_eq!(labels.denials[0].rule, "missing_label");
+        assert_eq!(labels.denials[0].path, "/metadata/labels/app");
+    }
+
+    #[test]
+    fn test_ratchet_on_update() {
+        let engine = engine(
+            r#"
+enforce_resource_limits: {enabled: false, mode: enforce}
+allowed_registries:
+  enabled: true
+  mode: enforce
+  ratchet: true
+  registries: ["gcr.io/myproject"]
+required_labels:
+  enabled: true
+  mode: enforce
+  ratchet: true
+  labels: [{key: "app"}, {key: "team"}]
+topology_spread: {enabled: false, mode: enforce}
+"#,
+        );
+        let old = pod(
+            json!({"team": "payments"}),
+            json!([{"name": "app", "image": "gcr.io/myproject/app:v1"}]),
+        );
+        let new = pod(json!({}), json!([{"name": "app", "image": "docker.io/app:v2"}]));
+        let req = request(new, json!({"operation": "UPDATE", "oldObject": old}));
+        let results = engine.evaluate_validate(&req);
+
+        let labels = result(&results, "labels");
+        assert_eq!(labels.denials.len(), 1);
+        assert!(labels.denials[0].message.contains("'team'"));
+        assert_eq!(labels.warnings.len(), 1);
+        assert!(labels.warnings[0].message.starts_with("pre-existing"));
+
+        let registry = result(&results, "image_registry");
+        assert_eq!(registry.denials.len(), 1);
+        assert!(registry.warnings.is_empty());
+
+        let create = request(
+            pod(json!({}), json!([{"name": "app", "image": "gcr.io/myproject/app:v1"}])),
+            json!({}),
+        );
+        assert_eq!(result(&engine.evaluate_validate(&create), "labels").denials.len(), 2);
+    }
+
+    #[test]
+    fn test_ratchet_ignores_container_index() {
+        let engine = engine(
+            r#"
+enforce_resource_limits: {enabled: false, mode: enforce}
+allowed_registries:
+  enabled: true
+  mode: enforce
+  ratchet: true
+  registries: ["gcr.io/myproject"]
+required_labels: {enabled: false, mode: enforce, labels: []}
+topology_spread: {enabled: false, mode: enforce}
+"#,
+        );
+        let old = pod(json!({}), json!([{"name": "app", "image": "docker.io/app:v1"}]));
+        let new = pod(
+            json!({}),
+            json!([
+                {"name": "sidecar", "image": "gcr.io/myproject/proxy:v1"},
+                {"name": "app", "image": "docker.io/app:v1"},
+            ]),
+        );
+        let req = request(new, json!({"operation": "UPDATE", "oldObject": old}));
+
+        let results = engine.evaluate_validate(&req);
+        let registry = result(&results, "image_registry");
+        assert!(registry.denials.is_empty());
+        assert_eq!(registry.warnings.len(), 1);
+        assert_eq!(registry.warnings[0].path, "/spec/containers/1/image");
+        assert_eq!(
+            container_relative_path("/spec/template/spec/initContainers/3/resources/limits"),
+            "initContainers/resources/limits"
+        );
+        assert_eq!(container_relative_path("/metadata/labels/app"), "/metadata/labels/app");
+    }
+
+    #[test]
+    fn test_operations() {
+        let engine = engine(
+            r#"
+enforce_resource_limits: {enabled: false, mode: enforce}
+allowed_registries:
+  enabled: true
+  mode: enforce
+  registries: ["gcr.io/myproject"]
+required_labels:
+  enabled: true
+  mode: enforce
+  operations: [CREATE]
+  labels: [{key: "app"}]
+topology_spread: {enabled: false, mode: enforce}
+"#,
+        );
+        let object = pod(json!({}), json!([{"name": "app", "image": "gcr.io/myproject/app:v1"}]));
+
+        let create = request(object.clone(), json!({}));
+        let names: Vec<_> = engine
+            .evaluate_validate(&create)
+            .iter()
+            .map(|r| r.policy_name)
+            .collect();
+        assert_eq!(names, vec!["image_registry", "labels"]);
+
+        let update = request(object.clone(), json!({"operation": "UPDATE", "oldObject": object}));
+        let names: Vec<_> = engine
+            .evaluate_validate(&update)
+            .iter()
+            .map(|r| r.policy_name)
+            .collect();
+        assert_eq!(names, vec!["image_registry"]);
+
+        let delete = request(Value::Null, json!({"operation": "DELETE"}));
+        assert!(engine.evaluate_validate(&delete).is_empty());
+    }
+
+    #[test]
+    fn test_override_rebuilds_changed_policies() {
+        let engine = engine(&format!(
+            "{POLICIES}overrides:\n  - name: strict\n    namespaces: [\"prod-*\"]\n    \
+             policies: {{allowed_registries: {{mode: enforce}}}}\n"
+        ));
+        let over = &engine.overrides[0];
+        for (global, scoped) in engine.policies.iter().zip(&over.policies) {
+            let shared = Arc::ptr_eq(global, scoped);
+            assert_eq!(shared, global.name() != "image_registry", "{}", global.name());
+        }
+
+        let req = request(
+            pod(json!({"app": "web"}), json!([{"name": "app", "image": "nginx:1.25"}])),
+            json!({"namespace": "prod-eu"}),
+        );
+        assert!(!result(&engine.evaluate_validate(&req), "image_registry").allowed());
+    }
+
+    #[test]
+    fn test_break_glass_bypass() {
+        let engine = engine(&format!("{POLICIES}break_glass: {{groups: [\"sre\"]}}\n"));
+        let bypassed = |groups: Value, annotations: Value| {
+            let mut object = pod(json!({}), json!([{"name": "app", "image": "nginx:1.25"}]));
+            object["metadata"]["annotations"] = annotations;
+            let req = request(
+                object,
+                json!({"userInfo": {"username": "bob", "groups": groups}}),
+            );
+            let results = engine.evaluate_validate(&req);
+            let labels = result(&results, "labels");
+            (labels.allowed(), labels.bypass, labels.warnings.len())
+        };
+        let annotations = json!({
+            "sentinel.io/bypass": "image_registry, labels",
+            "sentinel.io/bypass-reason": "INC-42 hotfix",
+        });
+
+        assert_eq!(
+            bypassed(json!(["sre"]), annotations.clone()),
+            (true, Some(BypassOutcome::Bypassed), 1)
+        );
+        assert_eq!(
+            bypassed(json!(["dev"]), annotations),
+            (false, Some(BypassOutcome::Unauthorized), 0)
+        );
+        assert_eq!(
+            bypassed(json!(["sre"]), json!({"sentinel.io/bypass": "labels"})),
+            (false, Some(BypassOutcome::MissingReason), 0)
+        );
+        assert_eq!(
+            bypassed(json!(["sre"]), json!({"sentinel.io/bypass": "resource_limits"})),
+            (false, None, 0)
+        );
+    }
+
+    #[test]
+    fn test_scale_requests_with_default_config() {
+        let mut config: SentinelConfig =
+            serde_yaml::from_str(include_str!("../config/policies.yaml")).unwrap();
+        config.policies.replicas.enabled = true;
+        let engine = PolicyEngine::new(config.policies).unwrap();
+
+        let scale = |replicas: u32| {
+            request(
+                json!({
+                    "apiVersion": "autoscaling/v1",
+                    "kind": "Scale",
+                    "metadata": {"name": "web", "namespace": "default"},
+                    "spec": {"replicas": replicas},
+                }),
+                json!({
+                    "operation": "UPDATE",
+                    "resource": {"group": "apps", "version": "v1", "resource": "deployments"},
+                    "subResource": "scale",
+                }),
+            )
+        };
+        let results = engine.evaluate_policy(&scale(3), replicas::NAME);
+        let names: Vec<_> = results.iter().map(|r| r.policy_name).collect();
+        assert_eq!(names, vec![replicas::NAME]);
+        assert!(results[0].allowed());
+        assert!(!engine.evaluate_policy(&scale(80), replicas::NAME)[0].allowed());
+
+        let autoscaler = request(
+            json!({
+                "apiVersion": "autoscaling/v2",
+                "kind": "HorizontalPodAutoscaler",
+                "metadata": {"name": "web", "namespace": "default"},
+                "spec": {
+                    "scaleTargetRef": {"kind": "Deployment", "name": "web"},
+                    "minReplicas": 2,
+                    "maxReplicas": 10,
+                },
+            }),
+            json!({"resource": {
+                "group": "autoscaling",
+                "version": "v2",
+                "resource": "horizontalpodautoscalers",
+            }}),
+        );
+        let results = engine.evaluate_policy(&autoscaler, replicas::NAME);
+        assert!(results.iter().all(PolicyResult::allowed));
+    }
+
+    #[test]
+    fn test_controller_created_pod_not_bypassed() {
+        // An SRE's bypass on a Deployment's pod template ends up on its pods,
+        // but those are requested by the ReplicaSet controller.
+        let engine = engine(&format!("{POLICIES}break_glass: {{groups: [\"sre\"]}}\n"));
+        let mut object = pod(json!({}), json!([{"name": "app", "image": "nginx:1.25"}]));
+        object["metadata"]["annotations"] = json!({
+            "sentinel.io/bypass": "labels",
+            "sentinel.io/bypass-reason": "INC-42 hotfix",
+        });
+        object["metadata"]["ownerReferences"] = json!([{
+            "apiVersion": "apps/v1",
+            "kind": "ReplicaSet",
+            "name": "web-5d4f8",
+            "uid": "rs-uid",
+            "controller": true,
+        }]);
+        let req = request(
+            object,
+            json!({"userInfo": {
+                "username": "system:serviceaccount:kube-system:replicaset-controller",
+                "groups": ["system:serviceaccounts", "system:serviceaccounts:kube-system"],
+            }}),
+        );
+
+        let results = engine.evaluate_validate(&req);
+        let labels = result(&results, "labels");
+        assert!(!labels.allowed());
+        assert_eq!(labels.bypass, Some(BypassOutcome::Unauthorized));
     }
 }
@@ -1,4 +1,4 @@
-use std::sync::Arc;
+use std::sync::{Arc, RwLock};
 use std::time::Instant;
 
 use axum::extract::State;
@@ -6,7 +6,7 @@ use axum::Json;
 use json_patch::Patch;
 use kube::core::admission::{AdmissionRequest, AdmissionResponse, AdmissionReview, Operation};
 use kube::core::DynamicObject;
-use tracing::{error, warn};
+use tracing::{error, info, warn};
 
 fn fail_open_response(warning: String) -> AdmissionResponse {
     let mut resp = AdmissionResponse::invalid("");
@@ -16,23 +16,50 @@ fn fail_open_response(warning: String) -> AdmissionResponse {
     resp
 }
 
-use crate::config::PolicyMode;
 use crate::engine::{PolicyEngine, PolicyResult};
 use crate::metrics::{
-    PolicyEvalLabels, PolicyLabels, RequestLabels, ResponseLabels, SentinelMetrics, WebhookLabels,
+    BypassLabels, PolicyEvalLabels, PolicyLabels, RequestLabels, ResponseLabels, SentinelMetrics,
+    ViolationLabels, WebhookLabels,
 };
+use crate::patches::{self, MergedPatches};
+use crate::policies::{replicas, Violation};
 
 pub struct AppState {
-    pub engine: PolicyEngine,
+    engine: RwLock<Arc<PolicyEngine>>,
     pub metrics: SentinelMetrics,
 }
 
+impl AppState {
+    pub fn new(engine: PolicyEngine, metrics: SentinelMetrics) -> Self {
+        Self {
+            engine: RwLock::new(Arc::new(engine)),
+            metrics,
+        }
+    }
+
+    /// The engine serving requests right now. In-flight requests keep the
+    /// engine they started with across a reload.
+    pub fn engine(&self) -> Arc<PolicyEngine> {
+        self.engine
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    pub fn swap_engine(&self, engine: PolicyEngine) {
+        *self.engine.write().unwrap_or_else(|e| e.into_inner()) = Arc::new(engine);
+    }
+}
+
 pub type SharedState = Arc<AppState>;
 
 #[derive(Clone, Copy)]
 enum WebhookType {
     Validate,
     Mutate,
+    /// Scale subresources and autoscalers, checked by the replicas policy
+    /// alone since the other policies do not apply to them.
+    ValidateReplicas,
 }
 
 impl WebhookType {
@@ -40,6 +67,7 @@ impl WebhookType {
         match self {
             WebhookType::Validate => "validate",
             WebhookType::Mutate => "mutate",
+            WebhookType::ValidateReplicas => "validate_replicas",
         }
     }
 }
@@ -58,6 +86,13 @@ pub async fn handle_mutate(
     handle_webhook(state, body, WebhookType::Mutate)
 }
 
+pub async fn handle_validate_replicas(
+    state: State<SharedState>,
+    body: Json<serde_json::Value>,
+) -> Json<serde_json::Value> {
+    handle_webhook(state, body, WebhookType::ValidateReplicas)
+}
+
 fn handle_webhook(
     State(state): State<SharedState>,
     body: Json<serde_json::Value>,
@@ -84,13 +119,18 @@ fn handle_webhook(
 
     record_request_metrics(&state, &req, wh);
 
-    let evaluate = match webhook_type {
-        WebhookType::Validate => PolicyEngine::evaluate_validate,
-        WebhookType::Mutate => PolicyEngine::evaluate_mutate,
-    };
+    let evaluate: fn(&PolicyEngine, &AdmissionRequest<DynamicObject>) -> Vec<PolicyResult> =
+        match webhook_type {
+            WebhookType::Validate => PolicyEngine::evaluate_validate,
+            WebhookType::Mutate => PolicyEngine::evaluate_mutate,
+            WebhookType::ValidateReplicas => {
+                |engine, req| engine.evaluate_policy(req, replicas::NAME)
+            }
+        };
 
+    let engine = state.engine();
     let results = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
-        evaluate(&state.engine, &req)
+        evaluate(&engine, &req)
     }));
 
     let results = match results {
@@ -108,7 +148,13 @@ fn handle_webhook(
     };
 
     record_policy_eval_metrics(&state, &results);
-    let response = build_response(&req, &results, webhook_type);
+    log_violations(&req, &results);
+    let merged = match webhook_type {
+        WebhookType::Mutate => patches::merge(&results),
+        WebhookType::Validate | WebhookType::ValidateReplicas => MergedPatches::default(),
+    };
+    record_patch_conflicts(&state, &req, &merged);
+    let response = build_response(&req, &results, merged);
     record_response_metrics(&state, response.allowed, wh);
     observe_request_duration(&state, wh, start);
 
@@ -122,33 +168,37 @@ fn review_to_json(review: AdmissionReview<DynamicObject>) -> Json<serde_json::Va
 fn build_response(
     req: &AdmissionRequest<DynamicObject>,
     results: &[PolicyResult],
-    webhook_type: WebhookType,
+    merged: MergedPatches,
 ) -> AdmissionResponse {
     let mut resp = AdmissionResponse::from(req);
     let mut warnings: Vec<String> = Vec::new();
     let mut denied_messages: Vec<String> = Vec::new();
-    let mut all_patches: Vec<json_patch::PatchOperation> = Vec::new();
 
     for result in results {
-        warnings.extend(result.warnings.iter().cloned());
-
-        if matches!(webhook_type, WebhookType::Mutate) {
-            all_patches.extend(result.patches.iter().cloned());
-        }
-
-        if !result.allowed {
-            if let Some(msg) = &result.message {
-                denied_messages.push(format!("{}: {msg}", result.policy_name));
-            } else {
-                denied_messages.push(format!("{}: denied", result.policy_name));
-            }
+        warnings.extend(
+            result
+                .warnings
+                .iter()
+                .map(|v| format!("{}: {}", result.policy_name, render_violation(v))),
+        );
+
+        if !result.allowed() {
+            let messages: Vec<String> = result.denials.iter().map(render_violation).collect();
+            denied_messages.push(format!("{}: {}", result.policy_name, messages.join("; ")));
         }
     }
 
+    warnings.extend(merged.conflicts.iter().map(|c| {
+        format!(
+            "sentinel: dropped {} patch at {} conflicting with {}",
+            c.policy, c.path, c.kept_policy
+        )
+    }));
+
     if !denied_messages.is_empty() {
         resp = resp.deny(denied_messages.join("; "));
-    } else if !all_patches.is_empty() {
-        resp = match resp.with_patch(Patch(all_patches)) {
+    } else if !merged.patches.is_empty() {
+        resp = match resp.with_patch(Patch(merged.patches)) {
             Ok(patched) => patched,
             Err(e) => {
                 error!("failed to serialize patches: {e}");
@@ -168,6 +218,58 @@ fn build_response(
     resp
 }
 
+fn render_violation(violation: &Violation) -> String {
+    match &violation.hint {
+        Some(hint) => format!("{} (hint: {hint})", violation.message),
+        None => violation.message.clone(),
+    }
+}
+
+fn log_violations(req: &AdmissionRequest<DynamicObject>, results: &[PolicyResult]) {
+    for result in results {
+        let denials = result.denials.iter().map(|v| ("deny", v));
+        let warnings = result.warnings.iter().map(|v| ("warn", v));
+        for (action, violation) in denials.chain(warnings) {
+            info!(
+                uid = %req.uid,
+                action,
+                policy = violation.policy,
+                rule = violation.rule,
+                path = %violation.path,
+                container = violation.container.as_deref(),
+                kind = %req.kind.kind,
+                namespace = req.namespace.as_deref().unwrap_or_default(),
+                name = %req.name,
+                message = %violation.message,
+                "policy violation"
+            );
+        }
+    }
+}
+
+fn record_patch_conflicts(
+    state: &AppState,
+    req: &AdmissionRequest<DynamicObject>,
+    merged: &MergedPatches,
+) {
+    for conflict in &merged.conflicts {
+        warn!(
+            uid = %req.uid,
+            policy = conflict.policy,
+            path = %conflict.path,
+            kept_policy = conflict.kept_policy,
+            "dropped conflicting patch"
+        );
+        state
+            .metrics
+            .patch_conflicts_total
+            .get_or_create(&PolicyLabels {
+                policy: conflict.policy,
+            })
+            .inc();
+    }
+}
+
 fn record_request_metrics(
     state: &AppState,
     req: &AdmissionRequest<DynamicObject>,
@@ -204,18 +306,22 @@ fn record_response_metrics(state: &AppState, allowed: bool, webhook: &'static st
 
 fn record_policy_eval_metrics(state: &AppState, results: &[PolicyResult]) {
     for result in results {
-        let mode = if result.allowed && result.warnings.is_empty() {
-            mode_str(state.engine.config.policy_mode(result.policy_name))
-        } else if !result.allowed {
+        let mode = if result.allowed() && result.warnings.is_empty() {
+            result.mode.as_str()
+        } else if !result.allowed() {
             "enforce"
         } else {
             "warn"
         };
 
-        let eval_result = if !result.allowed {
+        let eval_result = if result.exempted {
+            "exempted"
+        } else if !result.allowed() {
             "denied"
         } else if !result.warnings.is_empty() {
             "warning"
+        } else if !result.audit_violations.is_empty() {
+            "audited"
         } else {
             "allowed"
         };
@@ -224,19 +330,47 @@ fn record_policy_eval_metrics(state: &AppState, results: &[PolicyResult]) {
             .metrics
             .policy_evaluations_total
             .get_or_create(&PolicyEvalLabels {
-                policy: result.policy_name.as_str(),
+                policy: result.policy_name,
                 result: eval_result,
                 mode,
             })
             .inc();
 
+        if let Some(outcome) = result.bypass {
+            state
+                .metrics
+                .break_glass_total
+                .get_or_create(&BypassLabels {
+                    policy: result.policy_name,
+                    result: outcome.as_str(),
+                })
+                .inc();
+        }
+
         state
             .metrics
             .policy_evaluation_duration_seconds
             .get_or_create(&PolicyLabels {
-                policy: result.policy_name.as_str(),
+                policy: result.policy_name,
             })
             .observe(result.duration.as_secs_f64());
+
+        let violations = result
+            .denials
+            .iter()
+            .chain(&result.warnings)
+            .chain(&result.audit_violations);
+        for violation in violations {
+            state
+                .metrics
+                .policy_violations_total
+                .get_or_create(&ViolationLabels {
+                    policy: violation.policy,
+                    rule: violation.rule,
+                    mode: result.mode.as_str(),
+                })
+                .inc();
+        }
     }
 }
 
@@ -248,9 +382,119 @@ fn observe_request_duration(state: &AppState, webhook: &'static str, start: Inst
         .observe(start.elapsed().as_secs_f64());
 }
 
-fn mode_str(mode: &PolicyMode) -> &'static str {
-    match mode {
-        PolicyMode::Enforce => "enforce",
-        PolicyMode::Warn => "warn",
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{PoliciesConfig, PolicyMode};
+    use crate::patches::PatchConflict;
+    use crate::testing::{pod, request};
+    use prometheus_client::registry::Registry;
+    use serde_json::json;
+    use std::time::Duration;
+
+    fn result(policy: &'static str, mode: PolicyMode, violations: Vec<Violation>) -> PolicyResult {
+        let mut result = PolicyResult {
+            policy_name: policy,
+            mode,
+            exempted: false,
+            denials: vec![],
+            warnings: vec![],
+            audit_violations: vec![],
+            patches: vec![],
+            bypass: None,
+            duration: Duration::ZERO,
+        };
+        match mode {
+            PolicyMode::Enforce => result.denials = violations,
+            PolicyMode::Warn => result.warnings = violations,
+            PolicyMode::Audit => result.audit_violations = violations,
+        }
+        result
+    }
+
+    fn violation(policy: &'static str, message: &str) -> Violation {
+        Violation::new(policy, "rule", "/spec".to_string(), message)
+    }
+
+    #[test]
+    fn test_build_response_renders_violations() {
+        let req = request(pod(json!({}), json!([{"name": "app", "image": "nginx"}])), json!({}));
+        let results = [
+            result(
+                "labels",
+                PolicyMode::Enforce,
+                vec![
+                    violation("labels", "missing label 'team'").with_hint("add a team label"),
+                    violation("labels", "missing label 'app'"),
+                ],
+            ),
+            result(
+                "image_registry",
+                PolicyMode::Warn,
+                vec![violation("image_registry", "image uses latest").with_hint("pin a tag")],
+            ),
+            result("topology_spread", PolicyMode::Audit, vec![violation("topology_spread", "x")]),
+        ];
+        let merged = MergedPatches {
+            patches: vec![],
+            conflicts: vec![PatchConflict {
+                policy: "gpu",
+                path: "/spec/containers/0/resources".to_string(),
+                kept_policy: "resource_limits",
+            }],
+        };
+
+        let resp = build_response(&req, &results, merged);
+        assert!(!resp.allowed);
+        assert_eq!(
+            resp.result.message,
+            "labels: missing label 'team' (hint: add a team label); missing label 'app'"
+        );
+        assert_eq!(
+            resp.warnings.unwrap(),
+            vec![
+                "image_registry: image uses latest (hint: pin a tag)",
+                "sentinel: dropped gpu patch at /spec/containers/0/resources conflicting with \
+                 resource_limits",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_audit_mode_response() {
+        let config: PoliciesConfig = serde_yaml::from_str(
+            r#"
+enforce_resource_limits: {enabled: false, mode: enforce}
+allowed_registries: {enabled: false, mode: enforce, registries: []}
+required_labels: {enabled: true, mode: audit, labels: [{key: team}]}
+topology_spread: {enabled: false, mode: enforce}
+"#,
+        )
+        .unwrap();
+        let engine = PolicyEngine::new(config).unwrap();
+        let metrics = SentinelMetrics::new(&mut Registry::default(), engine.policies());
+        let state: SharedState = Arc::new(AppState::new(engine, metrics));
+
+        let req = request(pod(json!({}), json!([{"name": "app", "image": "nginx"}])), json!({}));
+        let review = json!({
+            "apiVersion": "admission.k8s.io/v1",
+            "kind": "AdmissionReview",
+            "request": serde_json::to_value(&req).unwrap(),
+        });
+        let Json(review) =
+            handle_webhook(State(state.clone()), Json(review), WebhookType::Validate);
+
+        let response = &review["response"];
+        assert_eq!(response["allowed"], true);
+        assert_eq!(response["uid"], "test");
+        assert!(response.get("warnings").is_none_or(|w| w.is_null()), "{response}");
+        assert!(response["status"]["message"].as_str().unwrap_or_default().is_empty());
+
+        let audited = state.metrics.policy_violations_total.get_or_create(&ViolationLabels {
+            policy: "labels",
+            rule: "missing_label",
+            mode: "audit",
+        });
+        assert_eq!(audited.get(), 1);
     }
 }
@@ -1,14 +1,24 @@
+mod bypass;
+mod catalog;
 mod config;
 mod engine;
 mod handlers;
 mod health;
 mod metrics;
+mod patches;
 mod policies;
+mod quantity;
+mod reload;
+mod selector;
+mod signature;
+#[cfg(test)]
+mod testing;
 mod tls;
 
 use std::net::SocketAddr;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
+use std::time::Duration;
 
 use axum::extract::DefaultBodyLimit;
 use axum::routing::{get, post};
@@ -160,17 +170,23 @@ async fn main() {
         listen_addr = %config.listen_addr,
         metrics_addr = %config.metrics_addr,
         log_level = %config.log_level,
-        policies.resource_limits.enabled = config.policies.resource_limits.enabled,
-        policies.resource_limits.mode = ?config.policies.resource_limits.mode,
-        policies.image_registry.enabled = config.policies.image_registry.enabled,
-        policies.image_registry.mode = ?config.policies.image_registry.mode,
-        policies.labels.enabled = config.policies.labels.enabled,
-        policies.labels.mode = ?config.policies.labels.mode,
-        policies.topology_spread.enabled = config.policies.topology_spread.enabled,
-        policies.topology_spread.mode = ?config.policies.topology_spread.mode,
         "k8s-sentinel starting"
     );
 
+    let engine = engine::PolicyEngine::new(config.policies.clone()).unwrap_or_else(|e| {
+        eprintln!("Invalid policy config in {}: {e}", cli.config);
+        std::process::exit(1);
+    });
+
+    for policy in engine.policies() {
+        info!(
+            policy = policy.name(),
+            enabled = policy.config().enabled(),
+            mode = policy.config().mode().as_str(),
+            "policy loaded"
+        );
+    }
+
     let tls_config = tls::load_tls_config(&config.tls_cert_path, &config.tls_key_path)
         .unwrap_or_else(|e| {
             eprintln!("Failed to load TLS config: {e}");
@@ -179,18 +195,20 @@ async fn main() {
     let tls_acceptor = TlsAcceptor::from(tls_config);
 
     let mut registry = Registry::default();
-    let sentinel_metrics = metrics::SentinelMetrics::new(&mut registry, &config.policies);
+    let sentinel_metrics = metrics::SentinelMetrics::new(&mut registry, engine.policies());
     let registry = Arc::new(registry);
 
-    let engine = engine::PolicyEngine::new(config.policies.clone());
+    let config_hash = std::fs::read(&cli.config)
+        .map(|contents| reload::config_hash(&contents))
+        .unwrap_or_default();
+    sentinel_metrics.set_config_hash(config_hash.clone());
 
-    let app_state = Arc::new(handlers::AppState {
-        engine,
-        metrics: sentinel_metrics,
-    });
+    let app_state = Arc::new(handlers::AppState::new(engine, sentinel_metrics));
+    let reload_state = app_state.clone();
 
     let webhook_router = Router::new()
         .route("/validate", post(handlers::handle_validate))
+        .route("/validate/replicas", post(handlers::handle_validate_replicas))
         .route("/mutate", post(handlers::handle_mutate))
         .layer(DefaultBodyLimit::max(2 * 1024 * 1024))
         .with_state(app_state);
@@ -218,6 +236,16 @@ async fn main() {
 
     let (shutdown_tx, shutdown_rx) = watch::channel(());
 
+    if config.config_reload_interval_secs > 0 {
+        tokio::spawn(reload::watch_config(
+            cli.config.clone(),
+            config_hash,
+            Duration::from_secs(config.config_reload_interval_secs),
+            reload_state,
+            shutdown_rx.clone(),
+        ));
+    }
+
     let https_shutdown_rx = shutdown_rx.clone();
     let http_shutdown_rx = shutdown_rx;
 
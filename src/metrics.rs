@@ -1,3 +1,5 @@
+use std::sync::Arc;
+
 use prometheus_client::encoding::EncodeLabelSet;
 use prometheus_client::metrics::counter::Counter;
 use prometheus_client::metrics::family::Family;
@@ -5,7 +7,7 @@ use prometheus_client::metrics::gauge::Gauge;
 use prometheus_client::metrics::histogram::Histogram;
 use prometheus_client::registry::Registry;
 
-use crate::config::{PoliciesConfig, PolicyName};
+use crate::policies::Policy;
 
 #[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
 pub struct RequestLabels {
@@ -27,6 +29,19 @@ pub struct PolicyEvalLabels {
     pub mode: &'static str,
 }
 
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+pub struct ViolationLabels {
+    pub policy: &'static str,
+    pub rule: &'static str,
+    pub mode: &'static str,
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+pub struct BypassLabels {
+    pub policy: &'static str,
+    pub result: &'static str,
+}
+
 #[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
 pub struct WebhookLabels {
     pub webhook: &'static str,
@@ -37,12 +52,28 @@ pub struct PolicyLabels {
     pub policy: &'static str,
 }
 
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+pub struct ReloadLabels {
+    pub result: &'static str,
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+pub struct ConfigLabels {
+    pub hash: String,
+}
+
 pub struct SentinelMetrics {
     pub admission_requests_total: Family<RequestLabels, Counter>,
     pub admission_responses_total: Family<ResponseLabels, Counter>,
     pub policy_evaluations_total: Family<PolicyEvalLabels, Counter>,
+    pub policy_violations_total: Family<ViolationLabels, Counter>,
+    pub patch_conflicts_total: Family<PolicyLabels, Counter>,
+    pub break_glass_total: Family<BypassLabels, Counter>,
     pub admission_request_duration_seconds: Family<WebhookLabels, Histogram>,
     pub policy_evaluation_duration_seconds: Family<PolicyLabels, Histogram>,
+    pub policies_enabled: Family<PolicyLabels, Gauge>,
+    pub config_reloads_total: Family<ReloadLabels, Counter>,
+    pub config_info: Family<ConfigLabels, Gauge>,
 }
 
 const DURATION_BUCKETS: [f64; 14] = [
@@ -54,7 +85,7 @@ fn new_duration_histogram() -> Histogram {
 }
 
 impl SentinelMetrics {
-    pub fn new(registry: &mut Registry, policies_config: &PoliciesConfig) -> Self {
+    pub fn new(registry: &mut Registry, policies: &[Arc<dyn Policy>]) -> Self {
         let admission_requests_total = Family::<RequestLabels, Counter>::default();
         registry.register(
             "sentinel_admission_requests",
@@ -76,6 +107,27 @@ impl SentinelMetrics {
             policy_evaluations_total.clone(),
         );
 
+        let policy_violations_total = Family::<ViolationLabels, Counter>::default();
+        registry.register(
+            "sentinel_policy_violations",
+            "Total number of individual policy violations by rule",
+            policy_violations_total.clone(),
+        );
+
+        let patch_conflicts_total = Family::<PolicyLabels, Counter>::default();
+        registry.register(
+            "sentinel_patch_conflicts",
+            "Total number of policy patches dropped for overlapping another policy's patch",
+            patch_conflicts_total.clone(),
+        );
+
+        let break_glass_total = Family::<BypassLabels, Counter>::default();
+        registry.register(
+            "sentinel_break_glass",
+            "Total number of break-glass bypass attempts on denied policies by result",
+            break_glass_total.clone(),
+        );
+
         let admission_request_duration_seconds =
             Family::<WebhookLabels, Histogram>::new_with_constructor(new_duration_histogram);
         registry.register(
@@ -99,20 +151,50 @@ impl SentinelMetrics {
             policies_enabled.clone(),
         );
 
-        for name in PolicyName::ALL {
-            policies_enabled
-                .get_or_create(&PolicyLabels {
-                    policy: name.as_str(),
-                })
-                .set(if policies_config.policy_enabled(name) { 1 } else { 0 });
-        }
+        let config_reloads_total = Family::<ReloadLabels, Counter>::default();
+        registry.register(
+            "sentinel_config_reloads",
+            "Total number of config reload attempts by result",
+            config_reloads_total.clone(),
+        );
 
-        Self {
+        let config_info = Family::<ConfigLabels, Gauge>::default();
+        registry.register(
+            "sentinel_config_info",
+            "Hash of the active policy config (always 1)",
+            config_info.clone(),
+        );
+
+        let metrics = Self {
             admission_requests_total,
             admission_responses_total,
             policy_evaluations_total,
+            policy_violations_total,
+            patch_conflicts_total,
+            break_glass_total,
             admission_request_duration_seconds,
             policy_evaluation_duration_seconds,
+            policies_enabled,
+            config_reloads_total,
+            config_info,
+        };
+        metrics.set_policies_enabled(policies);
+        metrics
+    }
+
+    pub fn set_policies_enabled(&self, policies: &[Arc<dyn Policy>]) {
+        self.policies_enabled.clear();
+        for policy in policies {
+            self.policies_enabled
+                .get_or_create(&PolicyLabels {
+                    policy: policy.name(),
+                })
+                .set(if policy.config().enabled() { 1 } else { 0 });
         }
     }
+
+    pub fn set_config_hash(&self, hash: String) {
+        self.config_info.clear();
+        self.config_info.get_or_create(&ConfigLabels { hash }).set(1);
+    }
 }
@@ -0,0 +1,340 @@
+use json_patch::jsonptr::Pointer;
+use json_patch::PatchOperation;
+use serde_json::{Map, Value};
+
+use crate::engine::PolicyResult;
+
+/// A patch dropped because it overlaps one already accepted from an earlier
+/// policy.
+pub struct PatchConflict {
+    pub policy: &'static str,
+    pub path: String,
+    pub kept_policy: &'static str,
+}
+
+#[derive(Default)]
+pub struct MergedPatches {
+    pub patches: Vec<PatchOperation>,
+    pub conflicts: Vec<PatchConflict>,
+}
+
+/// Combines the patches of all policies into one JSON patch.
+///
+/// Policies are taken in evaluation order and earlier policies win. A patch
+/// on the same path as an accepted one is dropped unless it is identical (or
+/// both append to an array with `-`). When one `add`/`replace` targets an
+/// ancestor of another, the descendant's value is folded into the ancestor's
+/// object value; if that would overwrite a different value, the later patch
+/// is dropped instead. Dropped patches are reported as conflicts so the
+/// caller can fail open with a warning.
+pub fn merge(results: &[PolicyResult]) -> MergedPatches {
+    let mut accepted: Vec<(&'static str, PatchOperation)> = Vec::new();
+    let mut conflicts = Vec::new();
+
+    for result in results {
+        for op in &result.patches {
+            if let Err(kept_policy) = place(&mut accepted, result.policy_name, op) {
+                conflicts.push(PatchConflict {
+                    policy: result.policy_name,
+                    path: op.path().to_string(),
+                    kept_policy,
+                });
+            }
+        }
+    }
+
+    MergedPatches {
+        patches: accepted.into_iter().map(|(_, op)| op).collect(),
+        conflicts,
+    }
+}
+
+/// Adds `op` to `accepted`, returning the policy whose patch blocks it.
+fn place(
+    accepted: &mut Vec<(&'static str, PatchOperation)>,
+    policy: &'static str,
+    op: &PatchOperation,
+) -> Result<(), &'static str> {
+    let path = op.path();
+    let mut op = op.clone();
+    let mut descendants = Vec::new();
+    // An accepted patch writing a subtree containing ours.
+    let mut ancestor = None;
+
+    for (i, (kept_policy, existing)) in accepted.iter_mut().enumerate() {
+        let existing_path = existing.path().to_buf();
+
+        if *path == *existing_path {
+            if *existing == op {
+                return Ok(());
+            }
+            let both_append =
+                matches!((&*existing, &op), (PatchOperation::Add(_), PatchOperation::Add(_)))
+                    && path.last().is_some_and(|t| t.encoded() == "-");
+            if both_append {
+                continue;
+            }
+            return Err(*kept_policy);
+        }
+
+        if path.starts_with(&existing_path) {
+            // Folded into once descendants accepted before it (which may also
+            // be descendants of ours) have been folded into ours.
+            ancestor = Some(i);
+            continue;
+        }
+
+        if existing_path.starts_with(path) {
+            let rel = existing_path.strip_prefix(path).expect("checked by starts_with");
+            let folded = match (value_mut(&mut op), value(existing)) {
+                (Some(target), Some(value)) => fold(target, rel, value),
+                _ => false,
+            };
+            if !folded {
+                return Err(*kept_policy);
+            }
+            descendants.push(i);
+        }
+    }
+
+    if let Some(i) = ancestor {
+        // The accepted patch writes a subtree containing ours: fold ours into
+        // it so it is not applied against a value that changed shape. Ours
+        // already carries the descendants, which are dropped with it.
+        let (kept_policy, existing) = &mut accepted[i];
+        let existing_path = existing.path().to_buf();
+        let rel = path.strip_prefix(&existing_path).expect("checked by starts_with");
+        let folded = match (value_mut(existing), value(&op)) {
+            (Some(target), Some(value)) => fold(target, rel, value),
+            _ => false,
+        };
+        if !folded {
+            return Err(*kept_policy);
+        }
+        for &i in descendants.iter().rev() {
+            accepted.remove(i);
+        }
+        return Ok(());
+    }
+
+    // Ours replaced accepted descendants that are now folded into it; keep it
+    // where the first of them was so relative order with other patches holds.
+    let position = descendants.first().copied().unwrap_or(accepted.len());
+    for &i in descendants.iter().rev() {
+        accepted.remove(i);
+    }
+    accepted.insert(position, (policy, op));
+    Ok(())
+}
+
+fn value(op: &PatchOperation) -> Option<&Value> {
+    match op {
+        PatchOperation::Add(op) => Some(&op.value),
+        PatchOperation::Replace(op) => Some(&op.value),
+        _ => None,
+    }
+}
+
+fn value_mut(op: &mut PatchOperation) -> Option<&mut Value> {
+    match op {
+        PatchOperation::Add(op) => Some(&mut op.value),
+        PatchOperation::Replace(op) => Some(&mut op.value),
+        _ => None,
+    }
+}
+
+/// Writes `value` at `rel` inside `target`, creating intermediate objects.
+/// Only walks objects, and refuses to overwrite a different existing value;
+/// `target` is left untouched on failure.
+fn fold(target: &mut Value, rel: &Pointer, value: &Value) -> bool {
+    let mut folded = target.clone();
+    let tokens: Vec<String> = rel.tokens().map(|t| t.decoded().into_owned()).collect();
+    let Some((last, parents)) = tokens.split_last() else {
+        return false;
+    };
+
+    let mut current = &mut folded;
+    for token in parents {
+        let Some(map) = current.as_object_mut() else {
+            return false;
+        };
+        current = map
+            .entry(token.clone())
+            .or_insert_with(|| Value::Object(Map::new()));
+    }
+
+    let Some(map) = current.as_object_mut() else {
+        return false;
+    };
+    match map.get(last) {
+        Some(existing) if existing != value => return false,
+        Some(_) => {}
+        None => {
+            map.insert(last.clone(), value.clone());
+        }
+    }
+
+    *target = folded;
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{PoliciesConfig, PolicyMode};
+    use crate::policies::{self, resource_limits};
+    use crate::testing::{pod, request, templates};
+    use json_patch::jsonptr::PointerBuf;
+    use json_patch::AddOperation;
+    use serde_json::json;
+    use std::time::Duration;
+
+    fn add(path: &str, value: Value) -> PatchOperation {
+        PatchOperation::Add(AddOperation {
+            path: PointerBuf::parse(path).unwrap(),
+            value,
+        })
+    }
+
+    fn result(policy: &'static str, patches: Vec<PatchOperation>) -> PolicyResult {
+        PolicyResult {
+            policy_name: policy,
+            mode: PolicyMode::Enforce,
+            exempted: false,
+            denials: vec![],
+            warnings: vec![],
+            audit_violations: vec![],
+            patches,
+            bypass: None,
+            duration: Duration::ZERO,
+        }
+    }
+
+    fn resources_patch() -> PatchOperation {
+        add(
+            "/spec/containers/0/resources",
+            json!({
+                "requests": {"cpu": "100m", "memory": "128Mi"},
+                "limits": {"cpu": "500m", "memory": "512Mi"},
+            }),
+        )
+    }
+
+    #[test]
+    fn test_mutating_policies() {
+        let config: PoliciesConfig = serde_yaml::from_str(
+            r#"
+enforce_resource_limits: {enabled: true, mode: enforce, inject_defaults: true}
+allowed_registries: {enabled: false, mode: enforce, registries: []}
+required_labels: {enabled: false, mode: enforce, labels: []}
+topology_spread: {enabled: true, mode: enforce, inject_if_missing: true}
+"#,
+        )
+        .unwrap();
+        let req = request(
+            pod(json!({"app": "web"}), json!([{"name": "app", "image": "nginx:1.25"}])),
+            json!({}),
+        );
+        let mut results: Vec<PolicyResult> = policies::registry(&config, &templates())
+            .unwrap()
+            .iter()
+            .map(|policy| result(policy.name(), policy.mutate(&req)))
+            .collect();
+        results.push(result(
+            "gpu",
+            vec![add("/spec/containers/0/resources/limits/nvidia.com~1gpu", json!("1"))],
+        ));
+
+        let merged = merge(&results);
+        assert!(merged.conflicts.is_empty());
+        assert_eq!(merged.patches.len(), 2);
+        assert_eq!(merged.patches[0].path().as_str(), "/spec/containers/0/resources");
+        assert_eq!(value(&merged.patches[0]).unwrap()["limits"]["nvidia.com/gpu"], json!("1"));
+        assert_eq!(merged.patches[1].path().as_str(), "/spec/topologySpreadConstraints");
+
+        let mut patched = req.object.as_ref().unwrap().data.clone();
+        json_patch::patch(&mut patched, &merged.patches).unwrap();
+        assert_eq!(patched["spec"]["containers"][0]["resources"]["requests"]["cpu"], "100m");
+    }
+
+    #[test]
+    fn test_descendant_folded_into_ancestor() {
+        let merged = merge(&[
+            result(resource_limits::NAME, vec![resources_patch()]),
+            result(
+                "gpu",
+                vec![add("/spec/containers/0/resources/limits/nvidia.com~1gpu", json!("1"))],
+            ),
+        ]);
+        assert!(merged.conflicts.is_empty());
+        assert_eq!(merged.patches.len(), 1);
+        assert_eq!(
+            merged.patches[0],
+            add(
+                "/spec/containers/0/resources",
+                json!({
+                    "requests": {"cpu": "100m", "memory": "128Mi"},
+                    "limits": {"cpu": "500m", "memory": "512Mi", "nvidia.com/gpu": "1"},
+                }),
+            )
+        );
+    }
+
+    #[test]
+    fn test_ancestor_absorbs_earlier_descendant() {
+        let merged = merge(&[
+            result(
+                "gpu",
+                vec![add("/spec/containers/0/resources/limits/nvidia.com~1gpu", json!("1"))],
+            ),
+            result(resource_limits::NAME, vec![resources_patch()]),
+        ]);
+        assert!(merged.conflicts.is_empty());
+        assert_eq!(merged.patches.len(), 1);
+        assert_eq!(merged.patches[0].path().as_str(), "/spec/containers/0/resources");
+        assert_eq!(value(&merged.patches[0]).unwrap()["limits"]["nvidia.com/gpu"], json!("1"));
+    }
+
+    #[test]
+    fn test_descendant_before_ancestor_is_removed() {
+        // A patch between an accepted descendant and an accepted ancestor
+        // must take the descendant along when it folds into the ancestor.
+        let gpu = add("/spec/containers/0/resources/limits/nvidia.com~1gpu", json!("1"));
+        let mut accepted = vec![("gpu", gpu), ("resources", add("/spec/containers/0", json!({})))];
+        place(&mut accepted, "limits", &add("/spec/containers/0/resources/limits", json!({})))
+            .unwrap();
+
+        assert_eq!(accepted.len(), 1);
+        assert_eq!(
+            accepted[0].1,
+            add(
+                "/spec/containers/0",
+                json!({"resources": {"limits": {"nvidia.com/gpu": "1"}}}),
+            )
+        );
+    }
+
+    #[test]
+    fn test_conflicting_values_keep_first_policy() {
+        let merged = merge(&[
+            result(resource_limits::NAME, vec![resources_patch()]),
+            result("sidecar", vec![add("/spec/containers/0/resources/limits/cpu", json!("2"))]),
+            result("other", vec![add("/spec/containers/0/resources", json!({}))]),
+        ]);
+        assert_eq!(merged.patches, vec![resources_patch()]);
+        assert_eq!(merged.conflicts.len(), 2);
+        assert_eq!(merged.conflicts[0].policy, "sidecar");
+        assert_eq!(merged.conflicts[0].kept_policy, resource_limits::NAME);
+        assert_eq!(merged.conflicts[1].path, "/spec/containers/0/resources");
+    }
+
+    #[test]
+    fn test_identical_and_appending_patches() {
+        let merged = merge(&[
+            result("one", vec![resources_patch(), add("/metadata/finalizers/-", json!("a"))]),
+            result("two", vec![resources_patch(), add("/metadata/finalizers/-", json!("b"))]),
+        ]);
+        assert!(merged.conflicts.is_empty());
+        assert_eq!(merged.patches.len(), 3);
+    }
+}
@@ -1,53 +1,387 @@
-use kube::core::admission::AdmissionRequest;
+use std::sync::Arc;
+use std::time::Duration;
+
+use json_patch::jsonptr::Pointer;
+use json_patch::{AddOperation, PatchOperation, ReplaceOperation};
+use kube::core::admission::{AdmissionRequest, Operation};
 use kube::core::DynamicObject;
+use regex::Regex;
+use serde_json::{json, Map, Value};
+
+use crate::catalog::DigestCatalog;
+use crate::config::{AllowedRegistriesPolicy, ConfigError, PolicyConfig};
+use crate::selector::glob_match;
+
+use super::{
+    field_pointer, get_containers, Container, ContainerList, PodTemplates, Policy, TemplatePaths,
+    Violation,
+};
+
+pub const NAME: &str = "image_registry";
+
+/// JSON map of container name to the image it had before being rewritten.
+pub const ORIGINAL_IMAGES_ANNOTATION: &str = "sentinel.io/original-images";
+
+const REGEX_PREFIX: &str = "regex:";
+
+pub struct ImageRegistry {
+    config: AllowedRegistriesPolicy,
+    registries: Vec<RegistryPattern>,
+    namespace_registries: Vec<(Vec<String>, Vec<RegistryPattern>)>,
+    allowed_tags: Vec<Regex>,
+    denied_tags: Vec<Regex>,
+    catalog: Option<Arc<DigestCatalog>>,
+    templates: PodTemplates,
+}
+
+impl ImageRegistry {
+    pub fn new(
+        config: AllowedRegistriesPolicy,
+        templates: PodTemplates,
+    ) -> Result<Self, ConfigError> {
+        let compile = |field: &str, registries: &[String]| {
+            registries
+                .iter()
+                .map(|r| RegistryPattern::new(field, r))
+                .collect::<Result<Vec<_>, _>>()
+        };
+        let registries = compile("allowed_registries registries", &config.registries)?;
+        let namespace_registries = config
+            .namespace_registries
+            .iter()
+            .map(|entry| {
+                let field = "allowed_registries namespace_registries";
+                Ok((entry.namespaces.clone(), compile(field, &entry.registries)?))
+            })
+            .collect::<Result<_, ConfigError>>()?;
+        Ok(Self {
+            registries,
+            namespace_registries,
+            allowed_tags: compile_tag_patterns("allowed_tags", &config.allowed_tags)?,
+            denied_tags: compile_tag_patterns("denied_tags", &config.denied_tags)?,
+            catalog: config.digest_catalog.as_ref().map(|catalog| {
+                let interval = Duration::from_secs(catalog.reload_interval_secs);
+                DigestCatalog::shared(&catalog.path, interval)
+            }),
+            config,
+            templates,
+        })
+    }
+
+    /// The image as rewritten by the first matching mirror rule and pinned
+    /// to its catalog digest, if that changes it.
+    fn rewrite(&self, image: &str) -> Option<String> {
+        let mirrored = self.mirror(image);
+        let current = mirrored.as_deref().unwrap_or(image);
+        self.pin(image, current).or(mirrored)
+    }
+
+    fn mirror(&self, image: &str) -> Option<String> {
+        let ImageRef { repository, reference, .. } = parse_image_ref(image);
+        let rule = self
+            .config
+            .mirrors
+            .iter()
+            .find(|rule| registry_matches(&repository, &rule.from))?;
+
+        let mut rewritten = rule.to.trim_end_matches('/').to_string();
+        for segment in repository.split('/').skip(rule.from.split('/').count()) {
+            rewritten.push('/');
+            rewritten.push_str(segment);
+        }
+        rewritten.push_str(&reference);
+        (rewritten != image).then_some(rewritten)
+    }
+
+    /// `image` as `<name>@<digest>`, with the digest looked up for the tag of
+    /// `original` (the image before mirroring). Untagged images count as
+    /// `latest`.
+    fn pin(&self, original: &str, image: &str) -> Option<String> {
+        let catalog = self.catalog.as_ref()?;
+        let ImageRef { repository, tag, has_digest, .. } = parse_image_ref(original);
+        if has_digest {
+            return None;
+        }
+        let digest = catalog.digest(&repository, effective_tag(&tag))?;
+        let ImageRef { reference, .. } = parse_image_ref(image);
+        Some(format!("{}@{digest}", &image[..image.len() - reference.len()]))
+    }
+
+    /// With `deny_unknown_tags`, the tag of an image pinned by neither a
+    /// digest nor a catalog entry.
+    fn unknown_tag(&self, image: &str) -> Option<String> {
+        let catalog = self.catalog.as_ref()?;
+        if !self.config.digest_catalog.as_ref()?.deny_unknown_tags {
+            return None;
+        }
+        let ImageRef { repository, tag, has_digest, .. } = parse_image_ref(image);
+        let tag = effective_tag(&tag);
+        let pinned = has_digest || catalog.digest(&repository, tag).is_some();
+        (!pinned).then(|| tag.to_string())
+    }
+
+    fn requires_digest(&self, namespace: Option<&str>) -> bool {
+        let namespaces = &self.config.require_digest_namespaces;
+        self.config.require_digest
+            && (namespaces.is_empty()
+                || namespace.is_some_and(|ns| namespaces.iter().any(|p| glob_match(p, ns))))
+    }
+
+    /// The global allowlist plus the entries of every namespace allowlist
+    /// matching `namespace`.
+    fn allowed(&self, namespace: Option<&str>) -> Vec<&RegistryPattern> {
+        let namespaced = self
+            .namespace_registries
+            .iter()
+            .filter(|(namespaces, _)| {
+                namespace.is_some_and(|ns| namespaces.iter().any(|p| glob_match(p, ns)))
+            })
+            .flat_map(|(_, patterns)| patterns);
+        self.registries.iter().chain(namespaced).collect()
+    }
+}
+
+fn compile_tag_patterns(field: &str, patterns: &[String]) -> Result<Vec<Regex>, ConfigError> {
+    patterns
+        .iter()
+        .map(|p| {
+            Regex::new(p).map_err(|source| ConfigError::InvalidRegex {
+                field: format!("allowed_registries {field}"),
+                pattern: p.clone(),
+                source,
+            })
+        })
+        .collect()
+}
+
+/// One allowlist entry, as written in the config.
+struct RegistryPattern {
+    source: String,
+    regex: Option<Regex>,
+}
+
+impl RegistryPattern {
+    /// `field` names the config list the entry came from, for errors.
+    fn new(field: &str, source: &str) -> Result<Self, ConfigError> {
+        let regex = source
+            .strip_prefix(REGEX_PREFIX)
+            .map(|pattern| {
+                Regex::new(&format!("^(?:{pattern})$")).map_err(|source| {
+                    ConfigError::InvalidRegex {
+                        field: field.to_string(),
+                        pattern: pattern.to_string(),
+                        source,
+                    }
+                })
+            })
+            .transpose()?;
+        Ok(Self {
+            source: source.to_string(),
+            regex,
+        })
+    }
+
+    fn matches(&self, registry: &str) -> bool {
+        match &self.regex {
+            Some(regex) => regex.is_match(registry),
+            None => registry_matches(registry, &self.source),
+        }
+    }
+}
+
+impl Policy for ImageRegistry {
+    fn name(&self) -> &'static str {
+        NAME
+    }
+
+    fn config(&self) -> &dyn PolicyConfig {
+        &self.config
+    }
+
+    fn evaluate(
+        &self,
+        request: &AdmissionRequest<DynamicObject>,
+        mutating: bool,
+    ) -> Vec<Violation> {
+        evaluate(self, request, mutating)
+    }
+
+    fn mutate(&self, request: &AdmissionRequest<DynamicObject>) -> Vec<PatchOperation> {
+        if self.config.mirrors.is_empty() && self.catalog.is_none() {
+            return Vec::new();
+        }
+
+        let object = match &request.object {
+            Some(obj) => obj,
+            None => return Vec::new(),
+        };
 
-use crate::config::AllowedRegistriesPolicy;
+        let paths = match self.templates.locate(request) {
+            Some(paths) => paths,
+            None => return Vec::new(),
+        };
+        let pod_spec = match paths.pod_spec(object) {
+            Some(spec) => spec,
+            None => return Vec::new(),
+        };
 
-use super::{container_name, get_containers, get_pod_spec, PolicyOutput};
+        let mut patches = Vec::new();
+        let mut originals = Map::new();
+        for container in &get_containers(pod_spec) {
+            if !may_rewrite(request, paths, container) {
+                continue;
+            }
+            let Some(image) = container.spec.get("image").and_then(|v| v.as_str()) else {
+                continue;
+            };
+            let Some(rewritten) = self.rewrite(image) else {
+                continue;
+            };
+            patches.push(PatchOperation::Replace(ReplaceOperation {
+                path: container.pointer(&paths.spec, &["image"]),
+                value: Value::String(rewritten),
+            }));
+            originals.insert(container.name().to_string(), Value::String(image.to_string()));
+        }
+
+        if !originals.is_empty() {
+            // Keep the originals recorded by earlier rewrites of the object.
+            let mut recorded: Map<String, Value> = object
+                .metadata
+                .annotations
+                .as_ref()
+                .and_then(|a| a.get(ORIGINAL_IMAGES_ANNOTATION))
+                .and_then(|v| serde_json::from_str(v).ok())
+                .unwrap_or_default();
+            recorded.extend(originals);
+            let recorded = Value::Object(recorded).to_string();
+            patches.push(annotation_patch(object, ORIGINAL_IMAGES_ANNOTATION, recorded));
+        }
+        patches
+    }
+}
+
+/// Whether the container's image may be rewritten. Ephemeral containers
+/// cannot be changed through the pod, and a running pod's images only on
+/// CREATE, since changing them restarts its containers; templates are
+/// rewritten on every write.
+fn may_rewrite(
+    request: &AdmissionRequest<DynamicObject>,
+    paths: &TemplatePaths,
+    container: &Container,
+) -> bool {
+    container.list != ContainerList::EphemeralContainers
+        && (!paths.is_pod || request.operation == Operation::Create)
+}
+
+/// Sets an annotation on the object, creating the annotations map if needed.
+fn annotation_patch(object: &DynamicObject, key: &str, value: String) -> PatchOperation {
+    let root = Pointer::root();
+    match object.metadata.annotations {
+        Some(_) => PatchOperation::Add(AddOperation {
+            path: field_pointer(root, &["metadata", "annotations", key]),
+            value: Value::String(value),
+        }),
+        None => PatchOperation::Add(AddOperation {
+            path: field_pointer(root, &["metadata", "annotations"]),
+            value: json!({ key: value }),
+        }),
+    }
+}
 
-pub fn evaluate(
-    config: &AllowedRegistriesPolicy,
+fn evaluate(
+    policy: &ImageRegistry,
     request: &AdmissionRequest<DynamicObject>,
-) -> PolicyOutput {
+    mutating: bool,
+) -> Vec<Violation> {
+    let config = &policy.config;
     let object = match &request.object {
         Some(obj) => obj,
-        None => return PolicyOutput::allowed(),
+        None => return Vec::new(),
     };
 
-    let kind = &request.kind.kind;
-    let pod_spec = match get_pod_spec(&object.data, kind) {
+    let paths = match policy.templates.locate(request) {
+        Some(paths) => paths,
+        None => return Vec::new(),
+    };
+    let pod_spec = match paths.pod_spec(object) {
         Some(spec) => spec,
-        None => return PolicyOutput::allowed(),
+        None => return Vec::new(),
     };
 
-    let containers = get_containers(pod_spec);
+    let allowed = policy.allowed(request.namespace.as_deref());
+    let requires_digest = policy.requires_digest(request.namespace.as_deref());
+    let prefix = &paths.spec;
     let mut violations = Vec::new();
 
-    for (_, container) in &containers {
-        let name = container_name(container);
-        let image = match container.get("image").and_then(|v| v.as_str()) {
+    for container in &get_containers(pod_spec) {
+        let name = container.name();
+        let label = container.kind();
+        let path = container.path(prefix, &["image"]);
+        let image = match container.spec.get("image").and_then(|v| v.as_str()) {
             Some(img) => img,
             None => {
-                violations.push(format!(
-                    "container '{name}' has no image specified"
-                ));
+                violations.push(
+                    Violation::new(
+                        NAME,
+                        "missing_image",
+                        path,
+                        format!("{label} '{name}' has no image specified"),
+                    )
+                    .with_container(name),
+                );
                 continue;
             }
         };
 
-        let ImageRef { registry, tag, has_digest } = parse_image_ref(image);
+        if let Some(tag) = policy.unknown_tag(image) {
+            violations.push(
+                Violation::new(
+                    NAME,
+                    "tag_not_in_catalog",
+                    path.clone(),
+                    format!(
+                        "{label} '{name}' image '{image}' tag '{tag}' is not in the digest \
+                         catalog"
+                    ),
+                )
+                .with_container(name)
+                .with_hint("use a tag published in the digest catalog or pin a digest"),
+            );
+        }
 
-        let registry_allowed = config
-            .registries
-            .iter()
-            .any(|allowed| registry_matches(&registry, allowed));
+        // On the mutate path, check the image the mirror rules and digest
+        // pinning will produce.
+        let rewritten = if mutating && may_rewrite(request, paths, container) {
+            policy.rewrite(image)
+        } else {
+            None
+        };
+        let image = rewritten.as_deref().unwrap_or(image);
+
+        let ImageRef { registry, tag, has_digest, .. } = parse_image_ref(image);
+
+        let registry_allowed = allowed.iter().any(|pattern| pattern.matches(&registry));
 
         if !registry_allowed {
-            violations.push(format!(
-                "container '{name}' image '{image}' uses registry '{registry}' \
-                 which is not in the allowed list [{}]",
-                config.registries.join(", ")
-            ));
+            violations.push(
+                Violation::new(
+                    NAME,
+                    "registry_not_allowed",
+                    path.clone(),
+                    format!(
+                        "{label} '{name}' image '{image}' uses registry '{registry}' \
+                         which is not in the allowed list [{}]",
+                        allowed
+                            .iter()
+                            .map(|p| p.source.as_str())
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    ),
+                )
+                .with_container(name)
+                .with_hint("push the image to an allowed registry"),
+            );
         }
 
         if !config.allow_latest_tag {
@@ -58,38 +392,101 @@ pub fn evaluate(
                 } else {
                     "latest"
                 };
-                violations.push(format!(
-                    "container '{name}' image '{image}' uses tag '{tag_display}'"
-                ));
+                violations.push(
+                    Violation::new(
+                        NAME,
+                        "latest_tag",
+                        path.clone(),
+                        format!("{label} '{name}' image '{image}' uses tag '{tag_display}'"),
+                    )
+                    .with_container(name)
+                    .with_hint("pin a specific version tag or digest"),
+                );
             }
         }
-    }
 
-    PolicyOutput {
-        violations,
-        patches: Vec::new(),
+        if requires_digest && !has_digest {
+            violations.push(
+                Violation::new(
+                    NAME,
+                    "digest_required",
+                    path.clone(),
+                    format!("{label} '{name}' image '{image}' is not pinned by digest"),
+                )
+                .with_container(name)
+                .with_hint("reference the image as <repository>@sha256:<digest>"),
+            );
+        }
+
+        // A digest pins the content, whatever the tag says.
+        if has_digest {
+            continue;
+        }
+        let tag = effective_tag(&tag);
+        let tag_allowed = policy.allowed_tags.is_empty()
+            || policy.allowed_tags.iter().any(|re| re.is_match(tag));
+        if !tag_allowed {
+            violations.push(
+                Violation::new(
+                    NAME,
+                    "tag_not_allowed",
+                    path.clone(),
+                    format!(
+                        "{label} '{name}' image '{image}' uses tag '{tag}' which matches none \
+                         of the allowed patterns [{}]",
+                        config.allowed_tags.join(", ")
+                    ),
+                )
+                .with_container(name),
+            );
+        }
+        if let Some(denied) = policy.denied_tags.iter().find(|re| re.is_match(tag)) {
+            violations.push(
+                Violation::new(
+                    NAME,
+                    "tag_denied",
+                    path,
+                    format!(
+                        "{label} '{name}' image '{image}' uses tag '{tag}' which matches \
+                         denied pattern '{denied}'"
+                    ),
+                )
+                .with_container(name)
+                .with_hint("use an immutable version tag or a digest"),
+            );
+        }
     }
+
+    violations
 }
 
-fn registry_matches(registry: &str, allowed: &str) -> bool {
-    if registry == allowed {
-        return true;
-    }
-    if registry.starts_with(allowed) {
-        let next_byte = registry.as_bytes().get(allowed.len());
-        return matches!(next_byte, Some(b'/'));
-    }
-    false
+/// Matches `registry` against `allowed` path segment by segment, so
+/// `gcr.io` allows `gcr.io/project` but not `gcr.io.evil.com`. Segments may
+/// use `*` and `?` globs, which never cross a `/`.
+pub(crate) fn registry_matches(registry: &str, allowed: &str) -> bool {
+    let mut segments = registry.split('/');
+    allowed
+        .split('/')
+        .all(|pattern| segments.next().is_some_and(|segment| glob_match(pattern, segment)))
+}
+
+/// The tag an image is pulled with: `latest` when it has none.
+fn effective_tag(tag: &str) -> &str {
+    if tag.is_empty() { "latest" } else { tag }
 }
 
-struct ImageRef {
-    registry: String,
-    tag: String,
-    has_digest: bool,
+pub(crate) struct ImageRef {
+    pub registry: String,
+    /// The registry and image name, e.g. `docker.io/library/nginx`.
+    pub repository: String,
+    pub tag: String,
+    pub has_digest: bool,
+    /// Everything after the name: `:tag`, `@digest` or both.
+    pub reference: String,
 }
 
-fn parse_image_ref(image: &str) -> ImageRef {
-    let has_digest = image.contains('@');
+pub(crate) fn parse_image_ref(image: &str) -> ImageRef {
+    let has_digest = image.contains("@sha256:");
 
     let image_no_digest = if let Some(pos) = image.find('@') {
         &image[..pos]
@@ -117,8 +514,15 @@ fn parse_image_ref(image: &str) -> ImageRef {
     };
 
     let registry = extract_registry(name_part);
+    let basename = name_part.rsplit('/').next().unwrap_or(name_part);
 
-    ImageRef { registry, tag: tag.to_string(), has_digest }
+    ImageRef {
+        repository: format!("{registry}/{basename}"),
+        registry,
+        tag: tag.to_string(),
+        has_digest,
+        reference: image[name_part.len()..].to_string(),
+    }
 }
 
 fn extract_registry(name_part: &str) -> String {
@@ -143,44 +547,55 @@ fn extract_registry(name_part: &str) -> String {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::testing::{pod, request, templates};
+    use serde_json::json;
 
     #[test]
     fn test_parse_image_ref() {
-        let ImageRef { registry, tag, has_digest } = parse_image_ref("nginx");
+        let ImageRef { registry, tag, has_digest, .. } = parse_image_ref("nginx");
         assert_eq!(registry, "docker.io/library");
         assert_eq!(tag, "");
         assert!(!has_digest);
 
-        let ImageRef { registry, tag, has_digest } = parse_image_ref("nginx:latest");
+        let ImageRef { registry, tag, has_digest, .. } = parse_image_ref("nginx:latest");
         assert_eq!(registry, "docker.io/library");
         assert_eq!(tag, "latest");
         assert!(!has_digest);
 
-        let ImageRef { registry, tag, has_digest } = parse_image_ref("nginx:1.25");
+        let ImageRef { registry, tag, has_digest, .. } = parse_image_ref("nginx:1.25");
         assert_eq!(registry, "docker.io/library");
         assert_eq!(tag, "1.25");
         assert!(!has_digest);
 
-        let ImageRef { registry, tag, has_digest } = parse_image_ref("myuser/myapp:v2");
+        let ImageRef { registry, tag, has_digest, .. } = parse_image_ref("myuser/myapp:v2");
         assert_eq!(registry, "docker.io/myuser");
         assert_eq!(tag, "v2");
         assert!(!has_digest);
 
-        let ImageRef { registry, tag, has_digest } = parse_image_ref("gcr.io/my-project/my-image:v1.0");
+        let ImageRef { registry, tag, has_digest, .. } = parse_image_ref("gcr.io/my-project/my-image:v1.0");
         assert_eq!(registry, "gcr.io/my-project");
         assert_eq!(tag, "v1.0");
         assert!(!has_digest);
 
-        let ImageRef { registry, tag, has_digest } =
+        let ImageRef { registry, tag, has_digest, .. } =
             parse_image_ref("gcr.io/my-project/my-image@sha256:abcdef1234567890");
         assert_eq!(registry, "gcr.io/my-project");
         assert_eq!(tag, "");
         assert!(has_digest);
 
-        let ImageRef { registry, tag, has_digest } = parse_image_ref("localhost:5000/myimage:v1");
+        let ImageRef { registry, tag, has_digest, .. } = parse_image_ref("localhost:5000/myimage:v1");
         assert_eq!(registry, "localhost:5000");
         assert_eq!(tag, "v1");
         assert!(!has_digest);
+
+        let ImageRef { repository, reference, .. } = parse_image_ref("nginx:1.25");
+        assert_eq!(repository, "docker.io/library/nginx");
+        assert_eq!(reference, ":1.25");
+
+        let ImageRef { repository, reference, .. } =
+            parse_image_ref("gcr.io/my-project/my-image:v1@sha256:abc");
+        assert_eq!(repository, "gcr.io/my-project/my-image");
+        assert_eq!(reference, ":v1@sha256:abc");
     }
 
     #[test]
@@ -190,5 +605,306 @@ mod tests {
         assert!(!registry_matches("gcr.io.evil.com", "gcr.io"));
         assert!(registry_matches("docker.io/library", "docker.io"));
         assert!(!registry_matches("docker.io.fake", "docker.io"));
+
+        let ecr = "*.dkr.ecr.*.amazonaws.com/team-*";
+        assert!(registry_matches("123.dkr.ecr.us-east-1.amazonaws.com/team-a", ecr));
+        assert!(registry_matches("123.dkr.ecr.us-east-1.amazonaws.com/team-a/sub", ecr));
+        assert!(!registry_matches("123.dkr.ecr.us-east-1.amazonaws.com/other", ecr));
+        assert!(!registry_matches("evil.com/x.dkr.ecr.y.amazonaws.com/team-a", ecr));
+        assert!(!registry_matches("123.dkr.ecr.us-east-1.amazonaws.com", ecr));
+
+        let regex =
+            RegistryPattern::new("registries", r"regex:gcr\.io/(dev|prod)-[a-z]+").unwrap();
+        assert!(regex.matches("gcr.io/prod-payments"));
+        assert!(!regex.matches("gcr.io/prod-payments/extra"));
+        assert!(!regex.matches("evil.io/gcr.io/prod-x"));
+        assert!(matches!(
+            RegistryPattern::new("registries", "regex:gcr\\.io/(dev"),
+            Err(ConfigError::InvalidRegex { .. })
+        ));
+    }
+
+    #[test]
+    fn test_namespace_registries() {
+        let config: AllowedRegistriesPolicy = serde_yaml::from_str(
+            r#"
+enabled: true
+mode: enforce
+allow_latest_tag: true
+registries: [gcr.io/shared]
+namespace_registries:
+  - namespaces: ["team-a-*"]
+    registries: ["*.dkr.ecr.*.amazonaws.com/team-a"]
+  - namespaces: ["team-b-*"]
+    registries: [ghcr.io/team-b]
+"#,
+        )
+        .unwrap();
+        let policy = ImageRegistry::new(config, templates()).unwrap();
+        let object = pod(
+            json!({}),
+            json!([{"name": "app", "image": "1.dkr.ecr.eu-west-1.amazonaws.com/team-a/app:v1"}]),
+        );
+
+        let team_a = request(object.clone(), json!({"namespace": "team-a-prod"}));
+        assert!(policy.evaluate(&team_a, false).is_empty());
+
+        let team_b = request(object, json!({"namespace": "team-b-prod"}));
+        let violations = policy.evaluate(&team_b, false);
+        assert_eq!(violations.len(), 1);
+        assert!(
+            violations[0]
+                .message
+                .ends_with("which is not in the allowed list [gcr.io/shared, ghcr.io/team-b]")
+        );
+    }
+
+    #[test]
+    fn test_all_container_lists() {
+        let config: AllowedRegistriesPolicy =
+            serde_yaml::from_str("{enabled: true, mode: enforce, registries: [gcr.io/myproject]}")
+                .unwrap();
+        let mut object =
+            pod(json!({}), json!([{"name": "app", "image": "gcr.io/myproject/app:v1"}]));
+        object["spec"]["initContainers"] = json!([
+            {"name": "setup", "image": "gcr.io/myproject/setup:v1"},
+            {"name": "proxy", "image": "docker.io/envoy:v1", "restartPolicy": "Always"},
+        ]);
+        object["spec"]["ephemeralContainers"] = json!([{"name": "debug", "image": "busybox:1.36"}]);
+
+        let policy = ImageRegistry::new(config, templates()).unwrap();
+        let violations = policy.evaluate(&request(object, json!({})), false);
+        let found: Vec<_> = violations.iter().map(|v| v.path.as_str()).collect();
+        assert_eq!(
+            found,
+            vec!["/spec/initContainers/1/image", "/spec/ephemeralContainers/0/image"]
+        );
+        assert!(violations[0].message.starts_with("sidecar container 'proxy'"));
+        assert!(violations[1].message.starts_with("ephemeral container 'debug'"));
+    }
+
+    #[test]
+    fn test_digest_and_tag_patterns() {
+        let config: AllowedRegistriesPolicy = serde_yaml::from_str(
+            r#"
+enabled: true
+mode: enforce
+registries: [gcr.io]
+require_digest: true
+require_digest_namespaces: ["prod-*"]
+allowed_tags: ['^v\d+\.\d+\.\d+$', '^main$']
+denied_tags: ['^(main|dev|stable)$']
+"#,
+        )
+        .unwrap();
+        let policy = ImageRegistry::new(config, templates()).unwrap();
+        let object = pod(
+            json!({}),
+            json!([
+                {"name": "app", "image": "gcr.io/p/app:v1.2.3"},
+                {"name": "web", "image": "gcr.io/p/web:main"},
+                {"name": "job", "image": "gcr.io/p/job:nightly"},
+                {"name": "db", "image": "gcr.io/p/db:dev@sha256:abcdef"},
+            ]),
+        );
+
+        let found = |namespace: &str| -> Vec<(String, &'static str)> {
+            let req = request(object.clone(), json!({"namespace": namespace}));
+            policy
+                .evaluate(&req, false)
+                .into_iter()
+                .map(|v| (v.container.unwrap(), v.rule))
+                .collect()
+        };
+        let expected = [("web", "tag_denied"), ("job", "tag_not_allowed")];
+        assert_eq!(found("default"), expected.map(|(c, r)| (c.to_string(), r)));
+        assert_eq!(
+            found("prod-eu"),
+            [
+                ("app", "digest_required"),
+                ("web", "digest_required"),
+                ("web", "tag_denied"),
+                ("job", "digest_required"),
+                ("job", "tag_not_allowed"),
+            ]
+            .map(|(c, r)| (c.to_string(), r))
+        );
+
+        // A typo in a deny rule must not silently stop denying.
+        let typo: AllowedRegistriesPolicy = serde_yaml::from_str(
+            "{enabled: true, mode: enforce, registries: [gcr.io], denied_tags: ['^(main|dev$']}",
+        )
+        .unwrap();
+        let err = ImageRegistry::new(typo, templates()).err().unwrap();
+        assert!(matches!(err, ConfigError::InvalidRegex { .. }));
+        assert!(err.to_string().starts_with("allowed_registries denied_tags has an invalid regex"));
+    }
+
+    #[test]
+    fn test_mirror_rewrites() {
+        let config: AllowedRegistriesPolicy = serde_yaml::from_str(
+            r#"
+enabled: true
+mode: enforce
+registries: [mirror.corp, gcr.io/myproject]
+mirrors:
+  - {from: docker.io/library, to: mirror.corp/dockerhub/library}
+  - {from: quay.io, to: mirror.corp/quay}
+"#,
+        )
+        .unwrap();
+        let policy = ImageRegistry::new(config, templates()).unwrap();
+        let mut object = pod(
+            json!({}),
+            json!([
+                {"name": "web", "image": "nginx:1.25"},
+                {"name": "app", "image": "gcr.io/myproject/app:v1"},
+            ]),
+        );
+        object["spec"]["initContainers"] =
+            json!([{"name": "setup", "image": "quay.io/org/tool@sha256:abc"}]);
+        let req = request(object, json!({}));
+
+        assert!(policy.evaluate(&req, true).is_empty());
+        assert_eq!(policy.evaluate(&req, false).len(), 2);
+
+        let patches = policy.mutate(&req);
+        let found: Vec<_> = patches
+            .iter()
+            .map(|op| {
+                let PatchOperation::Replace(op) = op else {
+                    return (op.path().to_string(), Value::Null);
+                };
+                (op.path.to_string(), op.value.clone())
+            })
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                ("/spec/containers/0/image".into(), json!("mirror.corp/dockerhub/library/nginx:1.25")),
+                ("/spec/initContainers/0/image".into(), json!("mirror.corp/quay/org/tool@sha256:abc")),
+                ("/metadata/annotations".into(), Value::Null),
+            ]
+        );
+        let PatchOperation::Add(annotations) = &patches[2] else {
+            unreachable!()
+        };
+        let originals: Value =
+            serde_json::from_str(annotations.value[ORIGINAL_IMAGES_ANNOTATION].as_str().unwrap())
+                .unwrap();
+        assert_eq!(originals, json!({"web": "nginx:1.25", "setup": "quay.io/org/tool@sha256:abc"}));
+
+        // Already rewritten images are left alone.
+        let mut patched = req.object.as_ref().unwrap().data.clone();
+        json_patch::patch(&mut patched, &patches[..2]).unwrap();
+        assert!(policy.mutate(&request(patched, json!({}))).is_empty());
+    }
+
+    #[test]
+    fn test_mirror_rewrites_on_update() {
+        let config: AllowedRegistriesPolicy = serde_yaml::from_str(
+            r#"
+enabled: true
+mode: enforce
+registries: [mirror.corp]
+mirrors: [{from: docker.io/library, to: mirror.corp/library}]
+"#,
+        )
+        .unwrap();
+        let policy = ImageRegistry::new(config, templates()).unwrap();
+        let image_paths = |patches: &[PatchOperation]| -> Vec<String> {
+            patches.iter().map(|p| p.path().to_string()).collect()
+        };
+
+        // Debug containers are skipped, and a running pod keeps its images.
+        let mut object = pod(json!({}), json!([{"name": "web", "image": "nginx:1.25"}]));
+        object["spec"]["ephemeralContainers"] = json!([{"name": "debug", "image": "busybox"}]);
+        let create = request(object.clone(), json!({}));
+        assert_eq!(
+            image_paths(&policy.mutate(&create)),
+            vec!["/spec/containers/0/image", "/metadata/annotations"]
+        );
+        let update = request(object.clone(), json!({"operation": "UPDATE", "oldObject": object}));
+        assert!(policy.mutate(&update).is_empty());
+        // ... so its current image is what gets checked.
+        let violations = policy.evaluate(&update, true);
+        assert!(violations.iter().any(|v| v.path == "/spec/containers/0/image"));
+
+        // Templates are rewritten on update, keeping earlier originals.
+        let deployment = json!({
+            "apiVersion": "apps/v1",
+            "kind": "Deployment",
+            "metadata": {
+                "name": "web",
+                "annotations": {ORIGINAL_IMAGES_ANNOTATION: r#"{"web":"nginx:1.25"}"#},
+            },
+            "spec": {"template": {"spec": {"containers": [
+                {"name": "web", "image": "mirror.corp/library/nginx:1.25"},
+                {"name": "cache", "image": "redis:7"},
+            ]}}},
+        });
+        let update = request(deployment, json!({"operation": "UPDATE"}));
+        let patches = policy.mutate(&update);
+        assert_eq!(
+            image_paths(&patches),
+            vec![
+                "/spec/template/spec/containers/1/image",
+                "/metadata/annotations/sentinel.io~1original-images",
+            ]
+        );
+        let PatchOperation::Add(annotation) = &patches[1] else {
+            unreachable!()
+        };
+        let originals: Value = serde_json::from_str(annotation.value.as_str().unwrap()).unwrap();
+        assert_eq!(originals, json!({"web": "nginx:1.25", "cache": "redis:7"}));
+    }
+
+    #[test]
+    fn test_digest_catalog_pinning() {
+        let digest = format!("sha256:{}", "ab".repeat(32));
+        let path = std::env::temp_dir().join(format!("sentinel-pin-{}.yaml", std::process::id()));
+        std::fs::write(&path, format!("nginx:1.25: {digest}\n")).unwrap();
+
+        let config: AllowedRegistriesPolicy = serde_json::from_value(json!({
+            "enabled": true,
+            "mode": "enforce",
+            "registries": ["mirror.corp"],
+            "mirrors": [{"from": "docker.io/library", "to": "mirror.corp/library"}],
+            "digest_catalog": {"path": path, "deny_unknown_tags": true},
+        }))
+        .unwrap();
+        let policy = ImageRegistry::new(config, templates()).unwrap();
+        let req = request(
+            pod(
+                json!({}),
+                json!([
+                    {"name": "web", "image": "nginx:1.25"},
+                    {"name": "cache", "image": "redis:7"},
+                ]),
+            ),
+            json!({}),
+        );
+
+        let patches = policy.mutate(&req);
+        let PatchOperation::Replace(op) = &patches[0] else {
+            unreachable!()
+        };
+        assert_eq!(op.value, json!(format!("mirror.corp/library/nginx@{digest}")));
+
+        let violations = policy.evaluate(&req, true);
+        let found: Vec<_> = violations.iter().map(|v| (v.container.as_deref(), v.rule)).collect();
+        assert_eq!(found, vec![(Some("cache"), "tag_not_in_catalog")]);
+
+        // Pinning follows the same rules as mirroring: not in debug
+        // containers, and not in a running pod.
+        let mut object = pod(json!({}), json!([{"name": "web", "image": "nginx:1.25"}]));
+        object["spec"]["ephemeralContainers"] = json!([{"name": "debug", "image": "nginx:1.25"}]);
+        let patches = policy.mutate(&request(object.clone(), json!({})));
+        let paths: Vec<_> = patches.iter().map(|p| p.path().to_string()).collect();
+        assert_eq!(paths, vec!["/spec/containers/0/image", "/metadata/annotations"]);
+        let update = request(object.clone(), json!({"operation": "UPDATE", "oldObject": object}));
+        assert!(policy.mutate(&update).is_empty());
+
+        std::fs::remove_file(&path).unwrap();
     }
 }
@@ -0,0 +1,276 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use kube::core::admission::AdmissionRequest;
+use kube::core::DynamicObject;
+use tracing::warn;
+
+use crate::config::{ConfigError, FailurePolicy, ImageSignaturePolicy, PolicyConfig};
+use crate::signature::{parse_public_key, SignatureVerifier, Verification};
+
+use super::image_registry::{parse_image_ref, registry_matches, ImageRef};
+use super::{get_containers, PodTemplates, Policy, Violation};
+
+pub const NAME: &str = "image_signature";
+
+pub struct ImageSignature {
+    config: ImageSignaturePolicy,
+    /// Only built for enabled policies, as it sets up an HTTPS client.
+    verifier: Option<Arc<SignatureVerifier>>,
+    templates: PodTemplates,
+}
+
+impl ImageSignature {
+    /// Invalid keys are config errors, even on a disabled policy, so that a
+    /// broken key is caught before an override or reload enables it.
+    pub fn new(
+        config: ImageSignaturePolicy,
+        templates: PodTemplates,
+    ) -> Result<Self, ConfigError> {
+        let keys = config
+            .public_keys
+            .iter()
+            .enumerate()
+            .map(|(index, pem)| {
+                parse_public_key(pem)
+                    .map_err(|source| ConfigError::InvalidPublicKey { index, source })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        if config.enabled && keys.is_empty() {
+            return Err(ConfigError::NoPublicKeys);
+        }
+
+        let verifier = config.enabled.then(|| {
+            Arc::new(SignatureVerifier::new(
+                keys,
+                config.insecure_registries.clone(),
+                Duration::from_millis(config.timeout_ms),
+                Duration::from_secs(config.cache_ttl_secs),
+            ))
+        });
+        Ok(Self {
+            config,
+            verifier,
+            templates,
+        })
+    }
+
+    fn covers(&self, repository: &str) -> bool {
+        self.config.images.is_empty()
+            || self.config.images.iter().any(|pattern| registry_matches(repository, pattern))
+    }
+}
+
+impl Policy for ImageSignature {
+    fn name(&self) -> &'static str {
+        NAME
+    }
+
+    fn config(&self) -> &dyn PolicyConfig {
+        &self.config
+    }
+
+    fn evaluate(
+        &self,
+        request: &AdmissionRequest<DynamicObject>,
+        mutating: bool,
+    ) -> Vec<Violation> {
+        // Images may still be pinned by other mutations, so signatures are
+        // only checked on the final object in /validate.
+        if mutating {
+            return Vec::new();
+        }
+        evaluate(self, request)
+    }
+}
+
+fn evaluate(policy: &ImageSignature, request: &AdmissionRequest<DynamicObject>) -> Vec<Violation> {
+    let Some(verifier) = &policy.verifier else {
+        return Vec::new();
+    };
+    let object = match &request.object {
+        Some(obj) => obj,
+        None => return Vec::new(),
+    };
+
+    let paths = match policy.templates.locate(request) {
+        Some(paths) => paths,
+        None => return Vec::new(),
+    };
+    let pod_spec = match paths.pod_spec(object) {
+        Some(spec) => spec,
+        None => return Vec::new(),
+    };
+
+    let prefix = &paths.spec;
+    let mut violations = Vec::new();
+    // Containers waiting for a signature lookup, in the order of `images`.
+    let mut pending = Vec::new();
+    let mut images = Vec::new();
+
+    for container in &get_containers(pod_spec) {
+        let name = container.name();
+        let label = container.kind();
+        let path = container.path(prefix, &["image"]);
+        // Missing images are reported by the image_registry policy.
+        let Some(image) = container.spec.get("image").and_then(|v| v.as_str()) else {
+            continue;
+        };
+
+        let ImageRef { repository, has_digest, .. } = parse_image_ref(image);
+        if !policy.covers(&repository) {
+            continue;
+        }
+        match image.split_once('@') {
+            Some((_, digest)) if has_digest => {
+                images.push((repository, digest.to_string()));
+                pending.push((violations.len(), name, label, path, image));
+            }
+            _ => violations.push(
+                Violation::new(
+                    NAME,
+                    "image_not_pinned",
+                    path,
+                    format!(
+                        "{label} '{name}' image '{image}' has no digest to verify a \
+                         signature for"
+                    ),
+                )
+                .with_container(name)
+                .with_hint("reference the image as <repository>@sha256:<digest>"),
+            ),
+        }
+    }
+    if images.is_empty() {
+        return violations;
+    }
+
+    // All images are checked at once, under one timeout for the request.
+    let results = verifier.verify_all_blocking(images);
+    let mut found = Vec::new();
+    for ((position, name, label, path, image), result) in pending.into_iter().zip(results) {
+        let (rule, message) = match result {
+            Ok(Verification::Verified) => continue,
+            Ok(Verification::Unsigned) => (
+                "signature_missing",
+                format!("{label} '{name}' image '{image}' is not signed"),
+            ),
+            Ok(Verification::Invalid(reason)) => (
+                "signature_invalid",
+                format!("{label} '{name}' image '{image}' has no valid signature: {reason}"),
+            ),
+            Err(e) if policy.config.failure_policy == FailurePolicy::Ignore => {
+                warn!(
+                    uid = %request.uid,
+                    image,
+                    "image signature not verified, failing open: {e}"
+                );
+                continue;
+            }
+            Err(e) => (
+                "signature_verification_failed",
+                format!("{label} '{name}' image '{image}' signature could not be verified: {e}"),
+            ),
+        };
+        let violation = Violation::new(NAME, rule, path, message)
+            .with_container(name)
+            .with_hint("sign the image with one of the configured keys");
+        found.push((position, violation));
+    }
+    // Keep container order among the unpinned images reported above.
+    for (offset, (position, violation)) in found.into_iter().enumerate() {
+        violations.insert(position + offset, violation);
+    }
+
+    violations
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::signature::testing::{serve_registry, SigningKey};
+    use crate::testing::{pod, request, templates};
+    use serde_json::json;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_signature_verification() {
+        let key = SigningKey::generate();
+        let signed = format!("sha256:{}", "1".repeat(64));
+        let unsigned = format!("sha256:{}", "2".repeat(64));
+        let (payload, signature) = key.sign(&signed);
+        let host = serve_registry(vec![(signed.clone(), payload, signature)]).await.to_string();
+
+        let config: ImageSignaturePolicy = serde_json::from_value(json!({
+            "enabled": true,
+            "mode": "enforce",
+            "public_keys": [key.public_key_pem()],
+            "images": [host],
+            "insecure_registries": [host],
+        }))
+        .unwrap();
+        let policy = ImageSignature::new(config, templates()).unwrap();
+        let object = pod(
+            json!({}),
+            json!([
+                {"name": "signed", "image": format!("{host}/team/app@{signed}")},
+                {"name": "unsigned", "image": format!("{host}/team/app@{unsigned}")},
+                {"name": "tagged", "image": format!("{host}/team/app:v1")},
+                {"name": "other", "image": "gcr.io/other/app:v1"},
+            ]),
+        );
+        let req = request(object, json!({}));
+
+        let violations = policy.evaluate(&req, false);
+        let found: Vec<_> = violations.iter().map(|v| (v.container.as_deref(), v.rule)).collect();
+        assert_eq!(
+            found,
+            vec![(Some("unsigned"), "signature_missing"), (Some("tagged"), "image_not_pinned")]
+        );
+        assert!(policy.evaluate(&req, true).is_empty());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_failure_policy() {
+        // Nothing listens on port 9, so every fetch fails.
+        let image = format!("127.0.0.1:9/team/app@sha256:{}", "3".repeat(64));
+        let object = pod(json!({}), json!([{"name": "app", "image": image}]));
+        let req = request(object, json!({}));
+
+        for (failure_policy, expected) in [("fail", 1), ("ignore", 0)] {
+            let config: ImageSignaturePolicy = serde_json::from_value(json!({
+                "enabled": true,
+                "mode": "enforce",
+                "public_keys": [SigningKey::generate().public_key_pem()],
+                "insecure_registries": ["127.0.0.1:9"],
+                "failure_policy": failure_policy,
+            }))
+            .unwrap();
+            let policy = ImageSignature::new(config, templates()).unwrap();
+            assert_eq!(policy.evaluate(&req, false).len(), expected, "{failure_policy}");
+        }
+    }
+
+    #[test]
+    fn test_invalid_keys_rejected() {
+        let config = |enabled: bool, keys: Vec<String>| ImageSignaturePolicy {
+            enabled,
+            public_keys: keys,
+            ..Default::default()
+        };
+        let valid = SigningKey::generate().public_key_pem();
+
+        assert!(matches!(
+            ImageSignature::new(config(true, vec![valid.clone(), "garbage".into()]), templates()),
+            Err(ConfigError::InvalidPublicKey { index: 1, .. })
+        ));
+        assert!(matches!(
+            ImageSignature::new(config(false, vec!["garbage".into()]), templates()),
+            Err(ConfigError::InvalidPublicKey { index: 0, .. })
+        ));
+        assert!(matches!(
+            ImageSignature::new(config(true, vec![]), templates()),
+            Err(ConfigError::NoPublicKeys)
+        ));
+        assert!(ImageSignature::new(config(true, vec![valid]), templates()).is_ok());
+    }
+}
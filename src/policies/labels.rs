@@ -1,76 +1,207 @@
+use json_patch::jsonptr::{Pointer, PointerBuf};
 use kube::core::admission::AdmissionRequest;
 use kube::core::DynamicObject;
 use regex::Regex;
-use tracing::warn;
 
-use crate::config::RequiredLabelsPolicy;
+use crate::config::{ConfigError, LabelTargets, PolicyConfig, RequiredLabelsPolicy};
 
-use super::PolicyOutput;
+use super::{field_path, labels_at, PodTemplates, Policy, Violation};
 
-pub struct CompiledLabel {
-    pub key: String,
-    pub pattern: Option<Regex>,
+pub const NAME: &str = "labels";
+
+pub struct RequiredLabels {
+    config: RequiredLabelsPolicy,
+    compiled: Vec<CompiledLabel>,
+    templates: PodTemplates,
+}
+
+impl RequiredLabels {
+    pub fn new(
+        config: RequiredLabelsPolicy,
+        templates: PodTemplates,
+    ) -> Result<Self, ConfigError> {
+        let compiled = compile_labels(&config)?;
+        Ok(Self {
+            config,
+            compiled,
+            templates,
+        })
+    }
+}
+
+impl Policy for RequiredLabels {
+    fn name(&self) -> &'static str {
+        NAME
+    }
+
+    fn config(&self) -> &dyn PolicyConfig {
+        &self.config
+    }
+
+    fn evaluate(
+        &self,
+        request: &AdmissionRequest<DynamicObject>,
+        _mutating: bool,
+    ) -> Vec<Violation> {
+        evaluate(self.config.targets, &self.compiled, &self.templates, request)
+    }
 }
 
-pub fn compile_labels(config: &RequiredLabelsPolicy) -> Vec<CompiledLabel> {
+struct CompiledLabel {
+    key: String,
+    pattern: Option<Regex>,
+}
+
+fn compile_labels(config: &RequiredLabelsPolicy) -> Result<Vec<CompiledLabel>, ConfigError> {
     config
         .labels
         .iter()
-        .map(|label| CompiledLabel {
-            key: label.key.clone(),
-            pattern: label.pattern.as_ref().map(|p| {
-                Regex::new(p).unwrap_or_else(|e| {
-                    warn!(
-                        pattern = %p,
-                        key = %label.key,
-                        "invalid regex pattern for required label, \
-                         falling back to literal match: {e}"
-                    );
-                    Regex::new(&regex::escape(p)).unwrap()
+        .map(|label| {
+            let pattern = label
+                .pattern
+                .as_ref()
+                .map(|p| {
+                    Regex::new(p).map_err(|source| ConfigError::InvalidRegex {
+                        field: format!("required_labels label '{}' pattern", label.key),
+                        pattern: p.clone(),
+                        source,
+                    })
                 })
-            }),
+                .transpose()?;
+            Ok(CompiledLabel {
+                key: label.key.clone(),
+                pattern,
+            })
         })
         .collect()
 }
 
-pub fn evaluate(
+fn evaluate(
+    targets: LabelTargets,
     compiled_labels: &[CompiledLabel],
+    templates: &PodTemplates,
     request: &AdmissionRequest<DynamicObject>,
-) -> PolicyOutput {
+) -> Vec<Violation> {
     let object = match &request.object {
         Some(obj) => obj,
-        None => return PolicyOutput::allowed(),
+        None => return Vec::new(),
     };
 
-    let labels = object.metadata.labels.as_ref();
     let resource_name = super::resource_name(request, object);
+    let object_labels = Pointer::from_static("/metadata/labels");
+
+    // Label maps to check, outermost first; nested templates may repeat a path.
+    let mut locations: Vec<PointerBuf> = Vec::new();
+    if targets != LabelTargets::PodTemplate {
+        locations.push(object_labels.to_buf());
+    }
+    if targets != LabelTargets::Object {
+        if let Some(paths) = templates.locate(request) {
+            for pointer in paths.outer_labels.iter().chain([&paths.labels]) {
+                if !locations.contains(pointer) {
+                    locations.push(pointer.clone());
+                }
+            }
+        }
+    }
 
     let mut violations = Vec::new();
 
-    for cl in compiled_labels {
-        match labels.and_then(|l| l.get(&cl.key)) {
-            None => {
-                violations.push(format!(
-                    "missing required label '{}' on {} '{}'",
-                    cl.key, request.kind.kind, resource_name,
-                ));
-            }
-            Some(value) => {
-                if let Some(pattern) = &cl.pattern {
-                    if !pattern.is_match(value) {
-                        violations.push(format!(
-                            "label '{}' on {} '{}' has value '{}' which does not match \
-                             required pattern '{}'",
-                            cl.key, request.kind.kind, resource_name, value, pattern.as_str(),
-                        ));
+    for location in &locations {
+        let labels = labels_at(object, location);
+        let target = if location == object_labels {
+            format!("{} '{}'", request.kind.kind, resource_name)
+        } else {
+            format!("{} '{}' at {}", request.kind.kind, resource_name, location)
+        };
+
+        for cl in compiled_labels {
+            let path = field_path(location, &[&cl.key]);
+            match labels.get(&cl.key).and_then(|v| v.as_str()) {
+                None => {
+                    violations.push(Violation::new(
+                        NAME,
+                        "missing_label",
+                        path,
+                        format!("missing required label '{}' on {target}", cl.key),
+                    ));
+                }
+                Some(value) => {
+                    if let Some(pattern) = &cl.pattern {
+                        if !pattern.is_match(value) {
+                            violations.push(Violation::new(
+                                NAME,
+                                "label_pattern_mismatch",
+                                path,
+                                format!(
+                                    "label '{}' on {target} has value '{}' which does not match \
+                                     required pattern '{}'",
+                                    cl.key, value, pattern.as_str(),
+                                ),
+                            ));
+                        }
                     }
                 }
             }
         }
     }
 
-    PolicyOutput {
-        violations,
-        patches: Vec::new(),
+    violations
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{request, templates};
+    use serde_json::json;
+
+    #[test]
+    fn test_pod_template_targets() {
+        let config: RequiredLabelsPolicy = serde_yaml::from_str(
+            "{enabled: true, mode: enforce, targets: both, labels: [{key: app}]}",
+        )
+        .unwrap();
+        let policy = RequiredLabels::new(config, templates()).unwrap();
+        let cronjob = request(
+            json!({
+                "apiVersion": "batch/v1",
+                "kind": "CronJob",
+                "metadata": {"name": "report", "labels": {"app": "report"}},
+                "spec": {"jobTemplate": {
+                    "metadata": {"labels": {"team": "data"}},
+                    "spec": {"template": {"metadata": {"labels": {"app": "report"}}, "spec": {}}},
+                }},
+            }),
+            json!({}),
+        );
+
+        let violations = policy.evaluate(&cronjob, false);
+        assert_eq!(violations.len(), 1);
+        assert_eq!(violations[0].path, "/spec/jobTemplate/metadata/labels/app");
+        assert_eq!(
+            violations[0].message,
+            "missing required label 'app' on CronJob 'report' at /spec/jobTemplate/metadata/labels"
+        );
+
+        let pod = request(
+            json!({"kind": "Pod", "metadata": {"name": "web", "labels": {}}, "spec": {}}),
+            json!({}),
+        );
+        let violations = policy.evaluate(&pod, false);
+        assert_eq!(violations.len(), 1);
+        assert_eq!(violations[0].message, "missing required label 'app' on Pod 'web'");
+    }
+
+    #[test]
+    fn test_invalid_pattern_rejected() {
+        let config: RequiredLabelsPolicy = serde_yaml::from_str(
+            r#"{enabled: true, mode: enforce, labels: [{key: version, pattern: '^v(\d+'}]}"#,
+        )
+        .unwrap();
+        let err = RequiredLabels::new(config, templates()).err().unwrap();
+        assert!(matches!(err, ConfigError::InvalidRegex { .. }));
+        assert!(err
+            .to_string()
+            .starts_with("required_labels label 'version' pattern has an invalid regex"));
     }
 }
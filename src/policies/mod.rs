@@ -1,57 +1,360 @@
 pub mod image_registry;
+pub mod image_signature;
 pub mod labels;
+pub mod replicas;
 pub mod resource_limits;
 pub mod topology_spread;
 
+use std::sync::Arc;
+
+use json_patch::jsonptr::{Pointer, PointerBuf};
 use json_patch::PatchOperation;
 use kube::core::admission::AdmissionRequest;
 use kube::core::DynamicObject;
 use serde_json::Value;
 
+use crate::config::{ConfigError, PodTemplateKind, PoliciesConfig, PolicyConfig};
+use crate::selector::glob_match;
+
+/// An admission policy evaluated by `PolicyEngine`.
+///
+/// To add a policy, implement this trait in a module under `policies/` and
+/// append it to [`registry`]; the engine, metrics and startup logging pick it
+/// up from there.
+pub trait Policy: Send + Sync {
+    /// Name used in metrics labels, log fields and response messages.
+    fn name(&self) -> &'static str;
+
+    /// The policy's own config section.
+    fn config(&self) -> &dyn PolicyConfig;
+
+    /// Checks the object and returns everything it violates.
+    ///
+    /// `mutating` is true on the `/mutate` path, where violations that
+    /// [`Policy::mutate`] will fix should be left out.
+    fn evaluate(&self, request: &AdmissionRequest<DynamicObject>, mutating: bool)
+        -> Vec<Violation>;
+
+    /// Patches applied on the `/mutate` path. Validation-only policies keep the default.
+    fn mutate(&self, _request: &AdmissionRequest<DynamicObject>) -> Vec<PatchOperation> {
+        Vec::new()
+    }
+}
+
+type Builder = fn(&PoliciesConfig, &PodTemplates) -> Result<Arc<dyn Policy>, ConfigError>;
+
+/// Every known policy in evaluation order, keyed by its config section.
+const POLICIES: [(&str, Builder); 6] = [
+    ("enforce_resource_limits", |config, templates| {
+        Ok(Arc::new(resource_limits::ResourceLimits::new(
+            config.resource_limits.clone(),
+            templates.clone(),
+        )?))
+    }),
+    ("allowed_registries", |config, templates| {
+        Ok(Arc::new(image_registry::ImageRegistry::new(
+            config.image_registry.clone(),
+            templates.clone(),
+        )?))
+    }),
+    ("required_labels", |config, templates| {
+        Ok(Arc::new(labels::RequiredLabels::new(config.labels.clone(), templates.clone())?))
+    }),
+    ("topology_spread", |config, templates| {
+        Ok(Arc::new(topology_spread::TopologySpread::new(
+            config.topology_spread.clone(),
+            templates.clone(),
+        )))
+    }),
+    ("replicas", |config, _| Ok(Arc::new(replicas::Replicas::new(config.replicas.clone())))),
+    ("image_signature", |config, templates| {
+        Ok(Arc::new(image_signature::ImageSignature::new(
+            config.image_signature.clone(),
+            templates.clone(),
+        )?))
+    }),
+];
+
+/// Builds every known policy from config, in evaluation order. Disabled
+/// policies are included so they can still be reported in metrics.
+pub fn registry(
+    config: &PoliciesConfig,
+    templates: &PodTemplates,
+) -> Result<Vec<Arc<dyn Policy>>, ConfigError> {
+    POLICIES
+        .iter()
+        .map(|(_, build)| build(config, templates))
+        .collect()
+}
+
+/// Like [`registry`], but only builds the policies whose config section is
+/// in `sections` and reuses the rest from `base`, itself built by
+/// [`registry`]. Overrides use this so that unchanged policies, and the
+/// catalogs and clients they hold, are shared with the global set.
+pub fn rebuild<S: AsRef<str>>(
+    base: &[Arc<dyn Policy>],
+    sections: &[S],
+    config: &PoliciesConfig,
+    templates: &PodTemplates,
+) -> Result<Vec<Arc<dyn Policy>>, ConfigError> {
+    POLICIES
+        .iter()
+        .zip(base)
+        .map(|((section, build), policy)| {
+            if sections.iter().any(|s| s.as_ref() == *section) {
+                build(config, templates)
+            } else {
+                Ok(policy.clone())
+            }
+        })
+        .collect()
+}
+
+/// A single policy violation, located in the object by a JSON pointer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Violation {
+    pub policy: &'static str,
+    /// Stable, machine-readable rule code, e.g. `registry_not_allowed`.
+    pub rule: &'static str,
+    /// JSON pointer to the offending field, e.g. `/spec/containers/0/image`.
+    pub path: String,
+    pub container: Option<String>,
+    pub message: String,
+    pub hint: Option<String>,
+}
+
+impl Violation {
+    pub fn new(
+        policy: &'static str,
+        rule: &'static str,
+        path: String,
+        message: impl Into<String>,
+    ) -> Self {
+        Self {
+            policy,
+            rule,
+            path,
+            container: None,
+            message: message.into(),
+            hint: None,
+        }
+    }
+
+    pub fn with_container(mut self, name: &str) -> Self {
+        self.container = Some(name.to_string());
+        self
+    }
+
+    pub fn with_hint(mut self, hint: impl Into<String>) -> Self {
+        self.hint = Some(hint.into());
+        self
+    }
+}
+
 pub struct PolicyOutput {
-    pub violations: Vec<String>,
+    pub violations: Vec<Violation>,
+    /// Violations the old object already had, split off by ratcheting.
+    pub preexisting: Vec<Violation>,
     pub patches: Vec<PatchOperation>,
 }
 
-impl PolicyOutput {
-    pub fn allowed() -> Self {
-        Self {
-            violations: Vec::new(),
-            patches: Vec::new(),
+/// Locates the pod template in objects of the kinds listed in `pod_templates`.
+#[derive(Clone)]
+pub struct PodTemplates {
+    kinds: Arc<[(PodTemplateKind, TemplatePaths)]>,
+}
+
+/// JSON pointers to the pod spec and pod labels in one kind's objects.
+pub struct TemplatePaths {
+    pub spec: PointerBuf,
+    pub labels: PointerBuf,
+    /// Labels of the templates the pod template is nested in, e.g. a
+    /// CronJob's `/spec/jobTemplate/metadata/labels`.
+    pub outer_labels: Vec<PointerBuf>,
+    /// Set when the object is a pod itself rather than a template for pods.
+    pub is_pod: bool,
+}
+
+impl PodTemplates {
+    pub fn new(kinds: &[PodTemplateKind]) -> Result<Self, ConfigError> {
+        let kinds = kinds
+            .iter()
+            .map(|kind| {
+                let parse = |pointer: &str| {
+                    Pointer::parse(pointer)
+                        .map(Pointer::to_buf)
+                        .map_err(|source| ConfigError::InvalidPodTemplate {
+                            kind: kind.kind.clone(),
+                            source,
+                        })
+                };
+                let template = parse(&kind.template)?;
+                let labels = match &kind.labels {
+                    Some(labels) => parse(labels)?,
+                    None => field_pointer(&template, &["metadata", "labels"]),
+                };
+                let paths = TemplatePaths {
+                    spec: field_pointer(&template, &["spec"]),
+                    labels,
+                    outer_labels: outer_templates(&template)
+                        .map(|outer| field_pointer(outer, &["metadata", "labels"]))
+                        .collect(),
+                    is_pod: template.is_root(),
+                };
+                Ok((kind.clone(), paths))
+            })
+            .collect::<Result<_, ConfigError>>()?;
+        Ok(Self { kinds })
+    }
+
+    /// Paths for the request's kind, or `None` if it has no pod template.
+    pub fn locate(&self, request: &AdmissionRequest<DynamicObject>) -> Option<&TemplatePaths> {
+        let gvk = &request.kind;
+        self.kinds
+            .iter()
+            .find(|(kind, _)| {
+                glob_match(&kind.group, &gvk.group)
+                    && glob_match(&kind.version, &gvk.version)
+                    && glob_match(&kind.kind, &gvk.kind)
+            })
+            .map(|(_, paths)| paths)
+    }
+}
+
+impl TemplatePaths {
+    pub fn pod_spec<'a>(&self, object: &'a DynamicObject) -> Option<&'a Value> {
+        object.data.pointer(self.spec.as_str())
+    }
+
+    /// The pod labels, or an empty object if there are none.
+    pub fn pod_labels(&self, object: &DynamicObject) -> Value {
+        labels_at(object, &self.labels)
+    }
+}
+
+/// Templates enclosing the one at `template`: every non-root prefix followed
+/// by a `spec` token, e.g. `/spec/jobTemplate` in
+/// `/spec/jobTemplate/spec/template`.
+fn outer_templates(template: &Pointer) -> impl Iterator<Item = &Pointer> {
+    let tokens: Vec<_> = template.tokens().collect();
+    (1..tokens.len())
+        .filter(move |&i| tokens[i].decoded() == "spec")
+        .filter_map(|i| template.get(..i))
+}
+
+/// The label map at `pointer` in the object, or an empty object if there is
+/// none.
+pub fn labels_at(object: &DynamicObject, pointer: &Pointer) -> Value {
+    // `metadata` is split out of `data` by DynamicObject.
+    let labels = match pointer.split_front() {
+        Some((first, rest)) if first.decoded() == "metadata" => {
+            serde_json::to_value(&object.metadata)
+                .ok()
+                .and_then(|m| m.pointer(rest.as_str()).cloned())
         }
+        _ => object.data.pointer(pointer.as_str()).cloned(),
+    };
+    labels
+        .filter(|l| l.is_object())
+        .unwrap_or_else(|| Value::Object(Default::default()))
+}
+
+/// JSON pointer for `tokens` below `prefix`, such as a pod spec from
+/// [`TemplatePaths`]. Tokens are escaped, so label keys can be passed as-is.
+pub fn field_path(prefix: &Pointer, tokens: &[&str]) -> String {
+    field_pointer(prefix, tokens).to_string()
+}
+
+/// [`field_path`] as a pointer, for building patches.
+pub fn field_pointer(prefix: &Pointer, tokens: &[&str]) -> PointerBuf {
+    let mut pointer = prefix.to_buf();
+    for token in tokens {
+        pointer.push_back(*token);
     }
+    pointer
+}
+
+/// Pod spec fields holding containers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContainerList {
+    Containers,
+    InitContainers,
+    EphemeralContainers,
 }
 
-pub fn get_pod_spec<'a>(data: &'a Value, kind: &str) -> Option<&'a Value> {
-    match kind {
-        "Pod" => data.get("spec"),
-        "Deployment" | "ReplicaSet" | "StatefulSet" | "DaemonSet" | "Job" => {
-            data.get("spec")?.get("template")?.get("spec")
+impl ContainerList {
+    pub const ALL: [ContainerList; 3] = [
+        ContainerList::Containers,
+        ContainerList::InitContainers,
+        ContainerList::EphemeralContainers,
+    ];
+
+    pub fn field(self) -> &'static str {
+        match self {
+            ContainerList::Containers => "containers",
+            ContainerList::InitContainers => "initContainers",
+            ContainerList::EphemeralContainers => "ephemeralContainers",
         }
-        "CronJob" => data
-            .get("spec")?
-            .get("jobTemplate")?
-            .get("spec")?
-            .get("template")?
-            .get("spec"),
-        _ => None,
     }
 }
 
-pub fn spec_prefix(kind: &str) -> &str {
-    match kind {
-        "Pod" => "spec",
-        "CronJob" => "spec/jobTemplate/spec/template/spec",
-        _ => "spec/template/spec",
+/// A container from any of the pod spec's container lists.
+pub struct Container<'a> {
+    pub list: ContainerList,
+    pub index: usize,
+    pub spec: &'a Value,
+}
+
+impl<'a> Container<'a> {
+    pub fn name(&self) -> &'a str {
+        container_name(self.spec)
+    }
+
+    /// An init container with `restartPolicy: Always`, which keeps running
+    /// next to the app containers.
+    pub fn is_sidecar(&self) -> bool {
+        self.list == ContainerList::InitContainers
+            && self.spec.get("restartPolicy").and_then(|p| p.as_str()) == Some("Always")
+    }
+
+    /// How the container is referred to in messages, e.g. `init container`.
+    pub fn kind(&self) -> &'static str {
+        match self.list {
+            ContainerList::Containers => "container",
+            ContainerList::InitContainers if self.is_sidecar() => "sidecar container",
+            ContainerList::InitContainers => "init container",
+            ContainerList::EphemeralContainers => "ephemeral container",
+        }
+    }
+
+    /// Pointer to `tokens` inside this container, below the pod spec `prefix`.
+    pub fn pointer(&self, prefix: &Pointer, tokens: &[&str]) -> PointerBuf {
+        let index = self.index.to_string();
+        let mut all = vec![self.list.field(), index.as_str()];
+        all.extend_from_slice(tokens);
+        field_pointer(prefix, &all)
+    }
+
+    pub fn path(&self, prefix: &Pointer, tokens: &[&str]) -> String {
+        self.pointer(prefix, tokens).to_string()
     }
 }
 
-pub fn get_containers(pod_spec: &Value) -> Vec<(usize, &Value)> {
-    pod_spec
-        .get("containers")
-        .and_then(|c| c.as_array())
-        .map(|arr| arr.iter().enumerate().collect())
-        .unwrap_or_default()
+/// Every container in the pod spec: app containers, then init containers
+/// (including sidecars), then ephemeral containers.
+pub fn get_containers(pod_spec: &Value) -> Vec<Container<'_>> {
+    ContainerList::ALL
+        .into_iter()
+        .flat_map(|list| {
+            pod_spec
+                .get(list.field())
+                .and_then(|c| c.as_array())
+                .into_iter()
+                .flatten()
+                .enumerate()
+                .map(move |(index, spec)| Container { list, index, spec })
+        })
+        .collect()
 }
 
 pub fn container_name(container: &Value) -> &str {
@@ -72,3 +375,67 @@ pub fn resource_name<'a>(request: &'a AdmissionRequest<DynamicObject>, object: &
         &request.name
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{request, templates};
+    use serde_json::json;
+
+    #[test]
+    fn test_pod_templates() {
+        let pod_template = json!({
+            "metadata": {"labels": {"app": "web"}},
+            "spec": {"containers": [{"name": "app", "image": "nginx:1.25"}]},
+        });
+
+        let rollout = request(
+            json!({
+                "apiVersion": "argoproj.io/v1alpha1",
+                "kind": "Rollout",
+                "metadata": {"name": "web"},
+                "spec": {"template": pod_template},
+            }),
+            json!({}),
+        );
+        let templates = templates();
+        let paths = templates.locate(&rollout).unwrap();
+        let object = rollout.object.as_ref().unwrap();
+        assert_eq!(paths.spec.as_str(), "/spec/template/spec");
+        assert_eq!(get_containers(paths.pod_spec(object).unwrap()).len(), 1);
+        assert_eq!(paths.pod_labels(object), json!({"app": "web"}));
+
+        let pod = request(
+            json!({"kind": "Pod", "metadata": {"labels": {"app": "web"}}, "spec": {}}),
+            json!({}),
+        );
+        let paths = templates.locate(&pod).unwrap();
+        assert_eq!(paths.labels.as_str(), "/metadata/labels");
+        assert_eq!(paths.pod_labels(pod.object.as_ref().unwrap()), json!({"app": "web"}));
+
+        let custom = PodTemplates::new(&[PodTemplateKind {
+            group: "*.example.com".to_string(),
+            version: "*".to_string(),
+            kind: "Worker".to_string(),
+            template: "/spec/runner/template".to_string(),
+            labels: Some("/spec/selector/matchLabels".to_string()),
+        }])
+        .unwrap();
+        let worker = request(
+            json!({
+                "apiVersion": "jobs.example.com/v1",
+                "kind": "Worker",
+                "metadata": {"name": "w"},
+                "spec": {
+                    "runner": {"template": pod_template},
+                    "selector": {"matchLabels": {"app": "w"}},
+                },
+            }),
+            json!({}),
+        );
+        let paths = custom.locate(&worker).unwrap();
+        assert_eq!(paths.spec.as_str(), "/spec/runner/template/spec");
+        assert_eq!(paths.pod_labels(worker.object.as_ref().unwrap()), json!({"app": "w"}));
+        assert!(custom.locate(&rollout).is_none());
+    }
+}
@@ -0,0 +1,359 @@
+use kube::core::admission::AdmissionRequest;
+use kube::core::DynamicObject;
+use serde_json::Value;
+
+use crate::config::{PolicyConfig, ReplicasPolicy};
+use crate::selector::glob_match;
+
+use super::{resource_name, Policy, Violation};
+
+pub const NAME: &str = "replicas";
+
+const HPA_KIND: &str = "HorizontalPodAutoscaler";
+
+/// Built-in scalable kinds as `(group, resource, kind)`, for matching scale
+/// subresource requests, which only name the group and resource.
+const SCALE_TARGETS: [(&str, &str, &str); 5] = [
+    ("apps", "deployments", "Deployment"),
+    ("apps", "statefulsets", "StatefulSet"),
+    ("apps", "replicasets", "ReplicaSet"),
+    ("", "replicationcontrollers", "ReplicationController"),
+    ("argoproj.io", "rollouts", "Rollout"),
+];
+
+pub struct Replicas {
+    config: ReplicasPolicy,
+}
+
+/// Inclusive replica bounds for one workload.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Bounds {
+    min: Option<u32>,
+    max: Option<u32>,
+}
+
+impl Bounds {
+    fn hint(self) -> String {
+        match (self.min, self.max) {
+            (Some(min), Some(max)) => format!("use between {min} and {max} replicas"),
+            (Some(min), None) => format!("use at least {min} replicas"),
+            (None, Some(max)) => format!("use at most {max} replicas"),
+            (None, None) => String::new(),
+        }
+    }
+}
+
+impl Replicas {
+    pub fn new(config: ReplicasPolicy) -> Self {
+        Self { config }
+    }
+
+    /// Bounds from the first rule matching the kind and namespace, or the
+    /// policy-wide ones.
+    fn bounds(&self, kind: &str, namespace: Option<&str>) -> Bounds {
+        let rule = self.config.rules.iter().find(|rule| {
+            let kind_matches =
+                rule.kinds.is_empty() || rule.kinds.iter().any(|p| glob_match(p, kind));
+            let namespace_matches = rule.namespaces.is_empty()
+                || namespace.is_some_and(|ns| rule.namespaces.iter().any(|p| glob_match(p, ns)));
+            kind_matches && namespace_matches
+        });
+        match rule {
+            Some(rule) => Bounds {
+                min: rule.min,
+                max: rule.max,
+            },
+            None => Bounds {
+                min: self.config.min,
+                max: self.config.max,
+            },
+        }
+    }
+
+    /// The configured kind served as `resource` in API `group`, e.g.
+    /// `Deployment` for apps `deployments`.
+    fn scale_target(&self, group: &str, resource: &str) -> Option<&str> {
+        let (_, _, kind) = SCALE_TARGETS
+            .iter()
+            .find(|(g, r, _)| *g == group && *r == resource)?;
+        self.config.kinds.iter().find(|k| k == kind).map(String::as_str)
+    }
+}
+
+impl Policy for Replicas {
+    fn name(&self) -> &'static str {
+        NAME
+    }
+
+    fn config(&self) -> &dyn PolicyConfig {
+        &self.config
+    }
+
+    fn evaluate(
+        &self,
+        request: &AdmissionRequest<DynamicObject>,
+        _mutating: bool,
+    ) -> Vec<Violation> {
+        evaluate(self, request)
+    }
+}
+
+fn evaluate(policy: &Replicas, request: &AdmissionRequest<DynamicObject>) -> Vec<Violation> {
+    let object = match &request.object {
+        Some(obj) => obj,
+        None => return Vec::new(),
+    };
+
+    let namespace = request.namespace.as_deref();
+    let name = resource_name(request, object);
+    let spec = &object.data["spec"];
+    let mut violations = Vec::new();
+
+    match request.sub_resource.as_deref() {
+        Some("scale") => {
+            let resource = &request.resource;
+            let Some(kind) = policy.scale_target(&resource.group, &resource.resource) else {
+                return Vec::new();
+            };
+            let bounds = policy.bounds(kind, namespace);
+            let subject = format!("scale of {kind} '{name}'");
+            // Scale omits a zero replica count.
+            let replicas = spec.get("replicas").and_then(|r| r.as_u64()).unwrap_or(0);
+            check_replicas(&subject, replicas, bounds, &mut violations);
+        }
+        // Status and other subresources do not change the replica count.
+        Some(_) => {}
+        None if request.kind.kind == HPA_KIND => {
+            check_autoscaler(policy, name, spec, namespace, &mut violations);
+        }
+        None => {
+            let kind = &request.kind.kind;
+            if !policy.config.kinds.contains(kind) {
+                return Vec::new();
+            }
+            let bounds = policy.bounds(kind, namespace);
+            let subject = format!("{kind} '{name}'");
+            // The API server defaults a missing count to 1.
+            let replicas = spec.get("replicas").and_then(|r| r.as_u64()).unwrap_or(1);
+            check_replicas(&subject, replicas, bounds, &mut violations);
+        }
+    }
+
+    violations
+}
+
+fn check_replicas(subject: &str, replicas: u64, bounds: Bounds, violations: &mut Vec<Violation>) {
+    if let Some(min) = bounds.min.filter(|&min| replicas < min as u64) {
+        violations.push(
+            Violation::new(
+                NAME,
+                "replicas_below_min",
+                "/spec/replicas".to_string(),
+                format!("{subject} replicas {replicas} is below minimum allowed {min}"),
+            )
+            .with_hint(bounds.hint()),
+        );
+    }
+    if let Some(max) = bounds.max.filter(|&max| replicas > max as u64) {
+        violations.push(
+            Violation::new(
+                NAME,
+                "replicas_exceeds_max",
+                "/spec/replicas".to_string(),
+                format!("{subject} replicas {replicas} exceeds maximum allowed {max}"),
+            )
+            .with_hint(bounds.hint()),
+        );
+    }
+}
+
+/// An autoscaler must keep its target within the target kind's bounds:
+/// `minReplicas` (default 1) no lower than the minimum, `maxReplicas` no
+/// higher than the maximum.
+fn check_autoscaler(
+    policy: &Replicas,
+    name: &str,
+    spec: &Value,
+    namespace: Option<&str>,
+    violations: &mut Vec<Violation>,
+) {
+    let Some(target) = spec.pointer("/scaleTargetRef/kind").and_then(|k| k.as_str()) else {
+        return;
+    };
+    let bounds = policy.bounds(target, namespace);
+
+    let min_replicas = spec.get("minReplicas").and_then(|r| r.as_u64()).unwrap_or(1);
+    if let Some(min) = bounds.min.filter(|&min| min_replicas < min as u64) {
+        violations.push(
+            Violation::new(
+                NAME,
+                "min_replicas_below_min",
+                "/spec/minReplicas".to_string(),
+                format!(
+                    "{HPA_KIND} '{name}' minReplicas {min_replicas} for {target} is below \
+                     minimum allowed {min}"
+                ),
+            )
+            .with_hint(bounds.hint()),
+        );
+    }
+
+    let max_replicas = spec.get("maxReplicas").and_then(|r| r.as_u64());
+    if let Some((max_replicas, max)) = max_replicas.zip(bounds.max) {
+        if max_replicas > max as u64 {
+            violations.push(
+                Violation::new(
+                    NAME,
+                    "max_replicas_exceeds_max",
+                    "/spec/maxReplicas".to_string(),
+                    format!(
+                        "{HPA_KIND} '{name}' maxReplicas {max_replicas} for {target} exceeds \
+                         maximum allowed {max}"
+                    ),
+                )
+                .with_hint(bounds.hint()),
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::request;
+    use serde_json::json;
+
+    fn policy() -> Replicas {
+        Replicas::new(
+            serde_yaml::from_str(
+                r#"
+enabled: true
+mode: enforce
+max: 20
+rules:
+  - name: production
+    kinds: [Deployment, StatefulSet]
+    namespaces: ["prod-*"]
+    min: 2
+    max: 50
+"#,
+            )
+            .unwrap(),
+        )
+    }
+
+    fn rules(violations: &[Violation]) -> Vec<&str> {
+        violations.iter().map(|v| v.rule).collect()
+    }
+
+    #[test]
+    fn test_workload_bounds() {
+        let deployment = |replicas: u64| {
+            json!({
+                "apiVersion": "apps/v1",
+                "kind": "Deployment",
+                "metadata": {"name": "web"},
+                "spec": {"replicas": replicas},
+            })
+        };
+        let policy = policy();
+
+        let violations = policy.evaluate(&request(deployment(500), json!({})), false);
+        assert_eq!(rules(&violations), vec!["replicas_exceeds_max"]);
+        assert_eq!(violations[0].path, "/spec/replicas");
+        assert_eq!(
+            violations[0].message,
+            "Deployment 'web' replicas 500 exceeds maximum allowed 20"
+        );
+
+        let prod = json!({"namespace": "prod-eu"});
+        assert!(policy.evaluate(&request(deployment(30), prod.clone()), false).is_empty());
+        let violations = policy.evaluate(&request(deployment(1), prod), false);
+        assert_eq!(rules(&violations), vec!["replicas_below_min"]);
+        assert_eq!(violations[0].hint.as_deref(), Some("use between 2 and 50 replicas"));
+    }
+
+    #[test]
+    fn test_missing_replicas_defaults_to_one() {
+        let statefulset = json!({
+            "apiVersion": "apps/v1",
+            "kind": "StatefulSet",
+            "metadata": {"name": "db"},
+            "spec": {"serviceName": "db"},
+        });
+        let violations =
+            policy().evaluate(&request(statefulset, json!({"namespace": "prod-eu"})), false);
+        assert_eq!(rules(&violations), vec!["replicas_below_min"]);
+        assert_eq!(
+            violations[0].message,
+            "StatefulSet 'db' replicas 1 is below minimum allowed 2"
+        );
+    }
+
+    #[test]
+    fn test_scale_subresource() {
+        let scale = json!({
+            "apiVersion": "autoscaling/v1",
+            "kind": "Scale",
+            "metadata": {"name": "db", "namespace": "prod-eu"},
+            "spec": {"replicas": 1},
+        });
+        let fields = json!({
+            "namespace": "prod-eu",
+            "operation": "UPDATE",
+            "resource": {"group": "apps", "version": "v1", "resource": "statefulsets"},
+            "subResource": "scale",
+        });
+
+        let violations = policy().evaluate(&request(scale.clone(), fields.clone()), false);
+        assert_eq!(rules(&violations), vec!["replicas_below_min"]);
+        assert_eq!(
+            violations[0].message,
+            "scale of StatefulSet 'db' replicas 1 is below minimum allowed 2"
+        );
+
+        let mut status = fields.clone();
+        status["subResource"] = json!("status");
+        assert!(policy().evaluate(&request(scale.clone(), status), false).is_empty());
+
+        // Scale to zero omits `replicas`.
+        let mut zero = scale.clone();
+        zero["spec"] = json!({});
+        let violations = policy().evaluate(&request(zero, fields.clone()), false);
+        assert_eq!(
+            violations[0].message,
+            "scale of StatefulSet 'db' replicas 0 is below minimum allowed 2"
+        );
+
+        // Matched on group and resource, not on a plural of the kind.
+        let rollout = |group: &str| {
+            let mut fields = fields.clone();
+            fields["resource"] =
+                json!({"group": group, "version": "v1alpha1", "resource": "rollouts"});
+            fields["namespace"] = json!("default");
+            let mut scale = scale.clone();
+            scale["spec"]["replicas"] = json!(30);
+            policy().evaluate(&request(scale, fields), false)
+        };
+        assert_eq!(rules(&rollout("argoproj.io")), vec!["replicas_exceeds_max"]);
+        assert!(rollout("example.com").is_empty());
+    }
+
+    #[test]
+    fn test_autoscaler() {
+        let hpa = json!({
+            "apiVersion": "autoscaling/v2",
+            "kind": "HorizontalPodAutoscaler",
+            "metadata": {"name": "web"},
+            "spec": {
+                "scaleTargetRef": {"apiVersion": "apps/v1", "kind": "Deployment", "name": "web"},
+                "maxReplicas": 100,
+            },
+        });
+
+        let violations = policy().evaluate(&request(hpa, json!({"namespace": "prod-us"})), false);
+        assert_eq!(rules(&violations), vec!["min_replicas_below_min", "max_replicas_exceeds_max"]);
+        assert_eq!(violations[0].path, "/spec/minReplicas");
+        assert_eq!(violations[1].path, "/spec/maxReplicas");
+    }
+}
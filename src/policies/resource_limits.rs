@@ -1,37 +1,258 @@
-use json_patch::jsonptr::PointerBuf;
-use json_patch::{AddOperation, PatchOperation};
+use std::collections::BTreeMap;
+
+use json_patch::jsonptr::Pointer;
+use json_patch::{AddOperation, PatchOperation, ReplaceOperation};
 use kube::core::admission::AdmissionRequest;
 use kube::core::DynamicObject;
-use serde_json::{json, Value};
+use regex::Regex;
+use serde_json::{Map, Value};
 
-use crate::config::ResourceLimitsPolicy;
+use crate::config::{ConfigError, DefaultProfile, PolicyConfig, ResourceLimitsPolicy, ResourceRule};
+use crate::quantity::Quantity;
+use crate::selector::glob_match;
 
-use super::{container_name, get_containers, get_pod_spec, spec_prefix, PolicyOutput};
+use super::{
+    field_path, get_containers, Container, ContainerList, PodTemplates, Policy, Violation,
+};
 
-pub fn evaluate(
-    config: &ResourceLimitsPolicy,
+pub const NAME: &str = "resource_limits";
+
+pub struct ResourceLimits {
+    config: ResourceLimitsPolicy,
+    rules: BTreeMap<String, ResourceRule>,
+    profiles: Vec<CompiledProfile>,
+    templates: PodTemplates,
+}
+
+impl ResourceLimits {
+    pub fn new(
+        config: ResourceLimitsPolicy,
+        templates: PodTemplates,
+    ) -> Result<Self, ConfigError> {
+        let rules = resource_rules(&config);
+        let profiles = config
+            .default_profiles
+            .iter()
+            .map(CompiledProfile::new)
+            .collect::<Result<_, _>>()?;
+        Ok(Self {
+            config,
+            rules,
+            profiles,
+            templates,
+        })
+    }
+
+    /// Values to inject into the container: the per-resource defaults,
+    /// overlaid with those of the first matching profile.
+    fn defaults(&self, namespace: Option<&str>, container: &Container) -> Defaults {
+        let section = |section: &str| -> Map<String, Value> {
+            self.rules
+                .iter()
+                .filter_map(|(resource, rule)| {
+                    let value = rule.default_for(section)?;
+                    Some((resource.clone(), Value::String(value.to_string())))
+                })
+                .collect()
+        };
+        let mut defaults = Defaults {
+            requests: section("requests"),
+            limits: section("limits"),
+        };
+
+        if let Some(profile) = self.profiles.iter().find(|p| p.matches(namespace, container)) {
+            for (resource, value) in &profile.requests {
+                defaults.requests.insert(resource.clone(), Value::String(value.clone()));
+            }
+            for (resource, value) in &profile.limits {
+                defaults.limits.insert(resource.clone(), Value::String(value.clone()));
+            }
+        }
+        defaults
+    }
+}
+
+/// Values injected into one container's `requests` and `limits`.
+struct Defaults {
+    requests: Map<String, Value>,
+    limits: Map<String, Value>,
+}
+
+impl Defaults {
+    fn section(&self, section: &str) -> &Map<String, Value> {
+        match section {
+            "requests" => &self.requests,
+            _ => &self.limits,
+        }
+    }
+}
+
+struct CompiledProfile {
+    namespaces: Vec<String>,
+    container_name: Option<Regex>,
+    image: Option<String>,
+    requests: BTreeMap<String, String>,
+    limits: BTreeMap<String, String>,
+}
+
+impl CompiledProfile {
+    fn new(profile: &DefaultProfile) -> Result<Self, ConfigError> {
+        let container_name = profile
+            .container_name
+            .as_ref()
+            .map(|p| {
+                Regex::new(p).map_err(|source| ConfigError::InvalidRegex {
+                    field: format!("default profile '{}' container_name", profile.name),
+                    pattern: p.clone(),
+                    source,
+                })
+            })
+            .transpose()?;
+        Ok(Self {
+            namespaces: profile.namespaces.clone(),
+            container_name,
+            image: profile.image.clone(),
+            requests: profile.default_request.clone(),
+            limits: profile.default_limit.clone(),
+        })
+    }
+
+    /// Every criterion given must match; a profile without any matches all
+    /// containers.
+    fn matches(&self, namespace: Option<&str>, container: &Container) -> bool {
+        let namespace_matches = self.namespaces.is_empty()
+            || namespace.is_some_and(|ns| self.namespaces.iter().any(|p| glob_match(p, ns)));
+        let name_matches = self
+            .container_name
+            .as_ref()
+            .is_none_or(|re| re.is_match(container.name()));
+        let image = container.spec.get("image").and_then(|i| i.as_str());
+        let image_matches = self
+            .image
+            .as_ref()
+            .is_none_or(|pattern| image.is_some_and(|i| glob_match(pattern, i)));
+
+        namespace_matches && name_matches && image_matches
+    }
+}
+
+impl Policy for ResourceLimits {
+    fn name(&self) -> &'static str {
+        NAME
+    }
+
+    fn config(&self) -> &dyn PolicyConfig {
+        &self.config
+    }
+
+    fn evaluate(
+        &self,
+        request: &AdmissionRequest<DynamicObject>,
+        mutating: bool,
+    ) -> Vec<Violation> {
+        evaluate(self, request, mutating)
+    }
+
+    fn mutate(&self, request: &AdmissionRequest<DynamicObject>) -> Vec<PatchOperation> {
+        if !self.config.inject_defaults {
+            return Vec::new();
+        }
+
+        let object = match &request.object {
+            Some(obj) => obj,
+            None => return Vec::new(),
+        };
+
+        let paths = match self.templates.locate(request) {
+            Some(paths) => paths,
+            None => return Vec::new(),
+        };
+        let pod_spec = match paths.pod_spec(object) {
+            Some(spec) => spec,
+            None => return Vec::new(),
+        };
+
+        let prefix = &paths.spec;
+        let mut patches = Vec::new();
+        for container in get_containers(pod_spec).iter().filter(|c| has_resources(c)) {
+            let defaults = self.defaults(request.namespace.as_deref(), container);
+            generate_resource_patches(&defaults, container, prefix, &mut patches);
+        }
+        patches
+    }
+}
+
+/// The per-resource rules, with the legacy cpu/memory fields folded in under
+/// any `resources` entries for the same names.
+fn resource_rules(config: &ResourceLimitsPolicy) -> BTreeMap<String, ResourceRule> {
+    let mut rules = BTreeMap::from([
+        (
+            "cpu".to_string(),
+            ResourceRule {
+                max: config
+                    .max_cpu
+                    .or(config.max_cpu_millicores.map(Quantity::from_milli)),
+                default_request: Some(config.default_cpu_request.clone()),
+                default_limit: Some(config.default_cpu_limit.clone()),
+                ..Default::default()
+            },
+        ),
+        (
+            "memory".to_string(),
+            ResourceRule {
+                max: config.max_memory.or(config.max_memory_mb.map(mebibytes)),
+                default_request: Some(config.default_memory_request.clone()),
+                default_limit: Some(config.default_memory_limit.clone()),
+                ..Default::default()
+            },
+        ),
+    ]);
+
+    for (name, rule) in &config.resources {
+        let merged = rules.entry(name.clone()).or_default();
+        merged.max = rule.max.or(merged.max);
+        merged.min_request = rule.min_request;
+        merged.default_request = rule.default_request.clone().or(merged.default_request.take());
+        merged.default_limit = rule.default_limit.clone().or(merged.default_limit.take());
+        merged.required = rule.required;
+    }
+    rules
+}
+
+fn evaluate(
+    policy: &ResourceLimits,
     request: &AdmissionRequest<DynamicObject>,
     mutating: bool,
-) -> PolicyOutput {
+) -> Vec<Violation> {
+    let ResourceLimits {
+        config,
+        rules,
+        templates,
+        ..
+    } = policy;
     let object = match &request.object {
         Some(obj) => obj,
-        None => return PolicyOutput::allowed(),
+        None => return Vec::new(),
     };
 
-    let kind = &request.kind.kind;
-    let pod_spec = match get_pod_spec(&object.data, kind) {
+    let paths = match templates.locate(request) {
+        Some(paths) => paths,
+        None => return Vec::new(),
+    };
+    let pod_spec = match paths.pod_spec(object) {
         Some(spec) => spec,
-        None => return PolicyOutput::allowed(),
+        None => return Vec::new(),
     };
 
+    let prefix = &paths.spec;
     let containers = get_containers(pod_spec);
-    let prefix = spec_prefix(kind);
     let mut violations = Vec::new();
-    let mut patches = Vec::new();
 
-    for (i, container) in &containers {
-        let name = container_name(container);
-        let resources = container.get("resources");
+    for container in containers.iter().filter(|c| has_resources(c)) {
+        let name = container.name();
+        let label = container.kind();
+        let resources = container.spec.get("resources");
+        let defaults = policy.defaults(request.namespace.as_deref(), container);
 
         let has_requests = resources
             .and_then(|r| r.get("requests"))
@@ -52,221 +273,789 @@ pub fn evaluate(
                     (true, false) => "limits",
                     _ => unreachable!(),
                 };
-                violations.push(format!(
-                    "container '{name}' missing resource {missing}"
-                ));
+                violations.push(
+                    Violation::new(
+                        NAME,
+                        "missing_resources",
+                        container.path(prefix, &["resources"]),
+                        format!("{label} '{name}' missing resource {missing}"),
+                    )
+                    .with_container(name)
+                    .with_hint("set resources.requests and resources.limits for cpu and memory"),
+                );
             }
         }
 
-        if let Some(max_cpu) = config.max_cpu_millicores {
-            for section in &["requests", "limits"] {
-                if let Some(cpu_str) = resources
-                    .and_then(|r| r.get(*section))
-                    .and_then(|s| s.get("cpu"))
-                    .and_then(|v| v.as_str())
-                {
-                    if let Some(cpu_m) = parse_cpu_millicores(cpu_str) {
-                        if cpu_m > max_cpu {
-                            violations.push(format!(
-                                "container '{name}' {section} cpu '{cpu_str}' ({cpu_m}m) \
-                                 exceeds maximum allowed {max_cpu}m"
-                            ));
+        for (resource, rule) in rules {
+            for section in ["requests", "limits"] {
+                let raw = resources
+                    .and_then(|r| r.get(section))
+                    .and_then(|s| s.get(resource));
+
+                let raw = match raw {
+                    Some(raw) => raw,
+                    // A missing request defaults to the limit, which is how
+                    // extended resources such as GPUs are usually requested.
+                    None if section == "requests"
+                        && resources
+                            .and_then(|r| r.get("limits"))
+                            .and_then(|s| s.get(resource))
+                            .is_some() =>
+                    {
+                        continue;
+                    }
+                    None => {
+                        let will_be_patched = mutating
+                            && config.inject_defaults
+                            && defaults.section(section).contains_key(resource);
+                        if rule.required && !will_be_patched {
+                            violations.push(
+                                Violation::new(
+                                    NAME,
+                                    "missing_required_resource",
+                                    container.path(prefix, &["resources", section, resource]),
+                                    format!(
+                                        "{label} '{name}' missing required {section} \
+                                         for {resource}"
+                                    ),
+                                )
+                                .with_container(name),
+                            );
                         }
+                        continue;
+                    }
+                };
+
+                // Unparseable values are reported by check_quantities.
+                let value = match Quantity::from_json(raw) {
+                    Ok(value) => value,
+                    Err(_) => continue,
+                };
+                let raw = display(raw);
+
+                if let Some(min) = rule.min_request.filter(|_| section == "requests") {
+                    if value < min {
+                        violations.push(
+                            Violation::new(
+                                NAME,
+                                "request_below_min",
+                                container.path(prefix, &["resources", section, resource]),
+                                format!(
+                                    "{label} '{name}' {resource} request '{raw}' is below \
+                                     minimum allowed '{min}'"
+                                ),
+                            )
+                            .with_container(name),
+                        );
                     }
                 }
+
+                let max = match rule.max {
+                    Some(max) => max,
+                    None => continue,
+                };
+                if value > max {
+                    let rule_name = match resource.as_str() {
+                        "cpu" => "cpu_exceeds_max",
+                        "memory" => "memory_exceeds_max",
+                        _ => "resource_exceeds_max",
+                    };
+                    violations.push(
+                        Violation::new(
+                            NAME,
+                            rule_name,
+                            container.path(prefix, &["resources", section, resource]),
+                            format!(
+                                "{label} '{name}' {section} {resource} '{raw}' exceeds \
+                                 maximum allowed '{max}'"
+                            ),
+                        )
+                        .with_container(name),
+                    );
+                }
             }
         }
 
-        if let Some(max_mem_mb) = config.max_memory_mb {
-            let max_mem_bytes = max_mem_mb * 1024 * 1024;
-            for section in &["requests", "limits"] {
-                if let Some(mem_str) = resources
-                    .and_then(|r| r.get(*section))
-                    .and_then(|s| s.get("memory"))
-                    .and_then(|v| v.as_str())
-                {
-                    if let Some(mem_bytes) = parse_memory_bytes(mem_str) {
-                        if mem_bytes > max_mem_bytes {
-                            violations.push(format!(
-                                "container '{name}' {section} memory '{mem_str}' \
-                                 ({} Mi) exceeds maximum allowed {max_mem_mb} Mi",
-                                mem_bytes / (1024 * 1024)
-                            ));
-                        }
-                    }
-                }
+        check_quantities(container, prefix, &mut violations);
+        check_requests_within_limits(container, prefix, &mut violations);
+        check_limit_request_ratio(config, container, prefix, &mut violations);
+    }
+
+    check_overhead(pod_spec, prefix, &mut violations);
+    check_pod_totals(config, pod_spec, &containers, prefix, &mut violations);
+
+    violations
+}
+
+/// Reports every request and limit that is not a valid quantity.
+fn check_quantities(container: &Container, prefix: &Pointer, violations: &mut Vec<Violation>) {
+    let name = container.name();
+    for section in ["requests", "limits"] {
+        let values = container
+            .spec
+            .get("resources")
+            .and_then(|r| r.get(section))
+            .and_then(|s| s.as_object());
+        for (resource, raw) in values.into_iter().flatten() {
+            if let Err(e) = Quantity::from_json(raw) {
+                violations.push(
+                    Violation::new(
+                        NAME,
+                        "invalid_quantity",
+                        container.path(prefix, &["resources", section, resource]),
+                        format!("{} '{name}' {section} {resource}: {e}", container.kind()),
+                    )
+                    .with_container(name)
+                    .with_hint("use a Kubernetes quantity such as 500m, 2 or 1Gi"),
+                );
             }
         }
+    }
+}
 
-        if config.inject_defaults {
-            generate_resource_patches(config, container, prefix, *i, &mut patches);
+fn check_overhead(pod_spec: &Value, prefix: &Pointer, violations: &mut Vec<Violation>) {
+    let overhead = pod_spec.get("overhead").and_then(|o| o.as_object());
+    for (resource, raw) in overhead.into_iter().flatten() {
+        if let Err(e) = Quantity::from_json(raw) {
+            violations.push(Violation::new(
+                NAME,
+                "invalid_quantity",
+                field_path(prefix, &["overhead", resource]),
+                format!("pod overhead {resource}: {e}"),
+            ));
         }
     }
+}
 
-    PolicyOutput {
-        violations,
-        patches,
+/// A quantity as written in the object, for messages.
+fn display(raw: &Value) -> String {
+    match raw {
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
     }
 }
 
-fn generate_resource_patches(
-    config: &ResourceLimitsPolicy,
-    container: &Value,
-    prefix: &str,
-    idx: usize,
-    patches: &mut Vec<PatchOperation>,
+/// Every resource with both a request and a limit must not request more than
+/// its limit, whether or not it has a configured rule.
+fn check_requests_within_limits(
+    container: &Container,
+    prefix: &Pointer,
+    violations: &mut Vec<Violation>,
 ) {
-    let resources = container.get("resources");
-    let has_resources = resources
-        .and_then(|r| r.as_object())
-        .is_some_and(|m| !m.is_empty());
+    let name = container.name();
+    let requests = container
+        .spec
+        .get("resources")
+        .and_then(|r| r.get("requests"))
+        .and_then(|r| r.as_object());
+    for resource in requests.into_iter().flat_map(|r| r.keys()) {
+        let request = resource_value(container, "requests", resource);
+        let limit = resource_value(container, "limits", resource);
+        let (Some(request), Some(limit)) = (request, limit) else {
+            continue;
+        };
+        if request > limit {
+            let quantity = |section: &str| display(&container.spec["resources"][section][resource]);
+            violations.push(
+                Violation::new(
+                    NAME,
+                    "request_exceeds_limit",
+                    container.path(prefix, &["resources", "requests", resource]),
+                    format!(
+                        "{} '{name}' {resource} request '{}' is greater than its limit '{}'",
+                        container.kind(),
+                        quantity("requests"),
+                        quantity("limits"),
+                    ),
+                )
+                .with_container(name)
+                .with_hint("lower the request or raise the limit"),
+            );
+        }
+    }
+}
 
-    let idx_str = idx.to_string();
+fn check_limit_request_ratio(
+    config: &ResourceLimitsPolicy,
+    container: &Container,
+    prefix: &Pointer,
+    violations: &mut Vec<Violation>,
+) {
+    let name = container.name();
+    for (resource, &max_ratio) in &config.max_limit_request_ratio {
+        let request = resource_value(container, "requests", resource);
+        let limit = resource_value(container, "limits", resource);
+        let (Some(request), Some(limit)) = (request, limit) else {
+            continue;
+        };
+        if request.is_zero() {
+            continue;
+        }
 
-    if !has_resources {
-        let mut path_parts: Vec<&str> = prefix.split('/').collect();
-        path_parts.extend_from_slice(&["containers", &idx_str, "resources"]);
-        patches.push(PatchOperation::Add(AddOperation {
-            path: PointerBuf::from_tokens(path_parts),
-            value: json!({
-                "requests": {
-                    "cpu": config.default_cpu_request,
-                    "memory": config.default_memory_request,
-                },
-                "limits": {
-                    "cpu": config.default_cpu_limit,
-                    "memory": config.default_memory_limit,
-                }
-            }),
-        }));
-        return;
+        let ratio = limit.as_f64() / request.as_f64();
+        if ratio > max_ratio {
+            violations.push(
+                Violation::new(
+                    NAME,
+                    "limit_request_ratio_exceeded",
+                    container.path(prefix, &["resources", "limits", resource]),
+                    format!(
+                        "{} '{name}' {resource} limit/request ratio {ratio:.2} exceeds \
+                         maximum allowed {max_ratio}",
+                        container.kind()
+                    ),
+                )
+                .with_container(name),
+            );
+        }
     }
+}
 
-    let resources = resources.unwrap();
+fn check_pod_totals(
+    config: &ResourceLimitsPolicy,
+    pod_spec: &Value,
+    containers: &[Container],
+    prefix: &Pointer,
+    violations: &mut Vec<Violation>,
+) {
+    let caps = [
+        (
+            "cpu",
+            "pod_cpu_exceeds_max",
+            config
+                .max_pod_cpu
+                .or(config.max_pod_cpu_millicores.map(Quantity::from_milli)),
+        ),
+        (
+            "memory",
+            "pod_memory_exceeds_max",
+            config.max_pod_memory.or(config.max_pod_memory_mb.map(mebibytes)),
+        ),
+    ];
 
-    let has_requests = resources.get("requests").is_some();
-    if !has_requests {
-        let mut path_parts: Vec<&str> = prefix.split('/').collect();
-        path_parts.extend_from_slice(&["containers", &idx_str, "resources", "requests"]);
-        patches.push(PatchOperation::Add(AddOperation {
-            path: PointerBuf::from_tokens(path_parts),
-            value: json!({
-                "cpu": config.default_cpu_request,
-                "memory": config.default_memory_request,
-            }),
-        }));
-    } else {
-        let requests = &resources["requests"];
-        if requests.get("cpu").is_none() {
-            let mut path_parts: Vec<&str> = prefix.split('/').collect();
-            path_parts
-                .extend_from_slice(&["containers", &idx_str, "resources", "requests", "cpu"]);
-            patches.push(PatchOperation::Add(AddOperation {
-                path: PointerBuf::from_tokens(path_parts),
-                value: Value::String(config.default_cpu_request.clone()),
-            }));
+    for (resource, rule, max) in caps {
+        let Some(max) = max else {
+            continue;
+        };
+        for section in ["requests", "limits"] {
+            let total = pod_total(pod_spec, containers, section, resource);
+            if total > max {
+                violations.push(Violation::new(
+                    NAME,
+                    rule,
+                    prefix.to_string(),
+                    format!(
+                        "pod {resource} {section} total {} (containers, init containers and \
+                         overhead) exceeds maximum allowed {}",
+                        total,
+                        max
+                    ),
+                ));
+            }
         }
-        if requests.get("memory").is_none() {
-            let mut path_parts: Vec<&str> = prefix.split('/').collect();
-            path_parts.extend_from_slice(&[
-                "containers",
-                &idx_str,
-                "resources",
-                "requests",
-                "memory",
-            ]);
-            patches.push(PatchOperation::Add(AddOperation {
-                path: PointerBuf::from_tokens(path_parts),
-                value: Value::String(config.default_memory_request.clone()),
-            }));
+    }
+}
+
+/// The pod's effective `section` of `resource` as the scheduler computes it:
+/// the larger of the app containers plus sidecars and the peak during init
+/// (each init container runs next to the sidecars started before it), plus
+/// `spec.overhead`.
+fn pod_total(
+    pod_spec: &Value,
+    containers: &[Container],
+    section: &str,
+    resource: &str,
+) -> Quantity {
+    let value = |c: &Container| resource_value(c, section, resource).unwrap_or_default();
+
+    let mut sidecars = Quantity::ZERO;
+    let mut init_peak = Quantity::ZERO;
+    for container in containers.iter().filter(|c| c.list == ContainerList::InitContainers) {
+        if container.is_sidecar() {
+            sidecars = sidecars + value(container);
+            init_peak = init_peak.max(sidecars);
+        } else {
+            init_peak = init_peak.max(sidecars + value(container));
         }
     }
 
-    let has_limits = resources.get("limits").is_some();
-    if !has_limits {
-        let mut path_parts: Vec<&str> = prefix.split('/').collect();
-        path_parts.extend_from_slice(&["containers", &idx_str, "resources", "limits"]);
-        patches.push(PatchOperation::Add(AddOperation {
-            path: PointerBuf::from_tokens(path_parts),
-            value: json!({
-                "cpu": config.default_cpu_limit,
-                "memory": config.default_memory_limit,
-            }),
-        }));
-    } else {
-        let limits = &resources["limits"];
-        if limits.get("cpu").is_none() {
-            let mut path_parts: Vec<&str> = prefix.split('/').collect();
-            path_parts
-                .extend_from_slice(&["containers", &idx_str, "resources", "limits", "cpu"]);
-            patches.push(PatchOperation::Add(AddOperation {
-                path: PointerBuf::from_tokens(path_parts),
-                value: Value::String(config.default_cpu_limit.clone()),
-            }));
+    let app: Quantity = containers
+        .iter()
+        .filter(|c| c.list == ContainerList::Containers)
+        .map(value)
+        .sum();
+    let overhead = pod_spec
+        .get("overhead")
+        .and_then(|o| o.get(resource))
+        .and_then(|v| Quantity::from_json(v).ok())
+        .unwrap_or_default();
+
+    (app + sidecars).max(init_peak) + overhead
+}
+
+/// The container's `section` quantity of `resource`, if set and valid.
+fn resource_value(container: &Container, section: &str, resource: &str) -> Option<Quantity> {
+    let value = container.spec.get("resources")?.get(section)?.get(resource)?;
+    Quantity::from_json(value).ok()
+}
+
+/// The legacy `*_memory_mb` fields are in MiB.
+fn mebibytes(mb: u64) -> Quantity {
+    Quantity::from_units(mb * 1024 * 1024)
+}
+
+/// Ephemeral containers may not set resources, so they are never checked or
+/// patched.
+fn has_resources(container: &Container) -> bool {
+    container.list != ContainerList::EphemeralContainers
+}
+
+fn generate_resource_patches(
+    defaults: &Defaults,
+    container: &Container,
+    prefix: &Pointer,
+    patches: &mut Vec<PatchOperation>,
+) {
+    let resources = container.spec.get("resources");
+    let has_resources = resources
+        .and_then(|r| r.as_object())
+        .is_some_and(|m| !m.is_empty());
+    let injected = injected_defaults(defaults, resources.filter(|_| has_resources));
+
+    if !has_resources {
+        let mut value = Map::new();
+        for (section, defaults) in ["requests", "limits"].into_iter().zip(injected) {
+            if !defaults.is_empty() {
+                value.insert(section.to_string(), Value::Object(defaults));
+            }
         }
-        if limits.get("memory").is_none() {
-            let mut path_parts: Vec<&str> = prefix.split('/').collect();
-            path_parts.extend_from_slice(&[
-                "containers",
-                &idx_str,
-                "resources",
-                "limits",
-                "memory",
-            ]);
+        if !value.is_empty() {
             patches.push(PatchOperation::Add(AddOperation {
-                path: PointerBuf::from_tokens(path_parts),
-                value: Value::String(config.default_memory_limit.clone()),
+                path: container.pointer(prefix, &["resources"]),
+                value: Value::Object(value),
             }));
         }
+        return;
     }
-}
 
-fn parse_cpu_millicores(value: &str) -> Option<u64> {
-    if let Some(millis) = value.strip_suffix('m') {
-        millis.parse::<f64>().ok().map(|v| v as u64)
-    } else {
-        value.parse::<f64>().ok().map(|v| (v * 1000.0) as u64)
+    let resources = resources.unwrap();
+
+    for (section, defaults) in ["requests", "limits"].into_iter().zip(injected) {
+        match resources.get(section) {
+            None if !defaults.is_empty() => {
+                patches.push(PatchOperation::Add(AddOperation {
+                    path: container.pointer(prefix, &["resources", section]),
+                    value: Value::Object(defaults),
+                }));
+            }
+            None => {}
+            // `requests: null` has no object to add keys to.
+            Some(Value::Null) if !defaults.is_empty() => {
+                patches.push(PatchOperation::Replace(ReplaceOperation {
+                    path: container.pointer(prefix, &["resources", section]),
+                    value: Value::Object(defaults),
+                }));
+            }
+            Some(Value::Object(_)) => {
+                for (resource, value) in defaults {
+                    patches.push(PatchOperation::Add(AddOperation {
+                        path: container.pointer(prefix, &["resources", section, &resource]),
+                        value,
+                    }));
+                }
+            }
+            Some(_) => {}
+        }
     }
 }
 
-fn parse_memory_bytes(value: &str) -> Option<u64> {
-    if let Some(n) = value.strip_suffix("Gi") {
-        n.parse::<u64>().ok().map(|v| v * 1024 * 1024 * 1024)
-    } else if let Some(n) = value.strip_suffix("Mi") {
-        n.parse::<u64>().ok().map(|v| v * 1024 * 1024)
-    } else if let Some(n) = value.strip_suffix("Ki") {
-        n.parse::<u64>().ok().map(|v| v * 1024)
-    } else if let Some(n) = value.strip_suffix('G') {
-        n.parse::<u64>().ok().map(|v| v * 1_000_000_000)
-    } else if let Some(n) = value.strip_suffix('M') {
-        n.parse::<u64>().ok().map(|v| v * 1_000_000)
-    } else if let Some(n) = value.strip_suffix('k') {
-        n.parse::<u64>().ok().map(|v| v * 1_000)
-    } else {
-        value.parse().ok()
+/// The `[requests, limits]` defaults for resources the container does not
+/// set. An injected limit below the container's request is raised to it, and
+/// an injected request above the container's limit is lowered to it, so the
+/// defaults never make a request exceed its limit.
+fn injected_defaults(defaults: &Defaults, resources: Option<&Value>) -> [Map<String, Value>; 2] {
+    let existing = |section: &str, resource: &str| {
+        resources.and_then(|r| r.get(section)).and_then(|s| s.get(resource)).cloned()
+    };
+    let missing = |section: &str| -> Map<String, Value> {
+        defaults
+            .section(section)
+            .iter()
+            .filter(|(resource, _)| existing(section, resource).is_none())
+            .map(|(resource, value)| (resource.clone(), value.clone()))
+            .collect()
+    };
+    let exceeds = |request: &Value, limit: &Value| {
+        match (Quantity::from_json(request), Quantity::from_json(limit)) {
+            (Ok(request), Ok(limit)) => request > limit,
+            _ => false,
+        }
+    };
+
+    let mut requests = missing("requests");
+    let mut limits = missing("limits");
+    for (resource, limit) in &mut limits {
+        let request = existing("requests", resource).or_else(|| requests.get(resource).cloned());
+        if let Some(request) = request.filter(|request| exceeds(request, limit)) {
+            *limit = request;
+        }
+    }
+    for (resource, request) in &mut requests {
+        if let Some(limit) = existing("limits", resource).filter(|limit| exceeds(request, limit)) {
+            *request = limit;
+        }
     }
+    [requests, limits]
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::testing::{pod, request, templates};
+    use serde_json::json;
+
+    #[test]
+    fn test_init_and_ephemeral_containers() {
+        let config: ResourceLimitsPolicy = serde_yaml::from_str(
+            "{enabled: true, mode: enforce, inject_defaults: true, max_cpu_millicores: 1000}",
+        )
+        .unwrap();
+        let resources = json!({
+            "requests": {"cpu": "100m", "memory": "64Mi"},
+            "limits": {"cpu": "2", "memory": "64Mi"},
+        });
+        let mut object =
+            pod(json!({}), json!([{"name": "app", "image": "app", "resources": resources}]));
+        object["spec"]["initContainers"] = json!([{"name": "setup", "image": "setup"}]);
+        object["spec"]["ephemeralContainers"] = json!([{"name": "debug", "image": "busybox"}]);
+        let req = request(object, json!({}));
+
+        let policy = ResourceLimits::new(config, templates()).unwrap();
+        let violations = policy.evaluate(&req, false);
+        assert_eq!(violations.len(), 2);
+        assert_eq!(violations[0].path, "/spec/containers/0/resources/limits/cpu");
+        assert_eq!(violations[1].path, "/spec/initContainers/0/resources");
+        assert!(violations[1].message.starts_with("init container 'setup'"));
+
+        let patches = policy.mutate(&req);
+        assert_eq!(patches.len(), 1);
+        assert_eq!(patches[0].path().as_str(), "/spec/initContainers/0/resources");
+    }
+
+    #[test]
+    fn test_pod_totals_and_ratio() {
+        let config: ResourceLimitsPolicy = serde_yaml::from_str(
+            "{enabled: true, mode: enforce, max_pod_cpu_millicores: 1700, \
+             max_limit_request_ratio: {memory: 2}}",
+        )
+        .unwrap();
+        let cpu = |cpu: &str| json!({"requests": {"cpu": cpu}, "limits": {"cpu": cpu}});
+        let mut object = pod(
+            json!({}),
+            json!([
+                {"name": "app", "resources": cpu("500m")},
+                {
+                    "name": "worker",
+                    "resources": {
+                        "requests": {"cpu": "500m", "memory": "100Mi"},
+                        "limits": {"cpu": "500m", "memory": "300Mi"},
+                    },
+                },
+            ]),
+        );
+        object["spec"]["initContainers"] = json!([
+            {"name": "proxy", "restartPolicy": "Always", "resources": cpu("200m")},
+            {"name": "migrate", "resources": cpu("1500m")},
+        ]);
+        object["spec"]["overhead"] = json!({"cpu": "100m"});
+        let req = request(object, json!({}));
+
+        // max(1000m + 200m, 200m + 1500m) + 100m overhead = 1800m
+        let violations = ResourceLimits::new(config, templates()).unwrap().evaluate(&req, false);
+        let rules: Vec<_> = violations.iter().map(|v| v.rule).collect();
+        assert_eq!(
+            rules,
+            vec!["limit_request_ratio_exceeded", "pod_cpu_exceeds_max", "pod_cpu_exceeds_max"]
+        );
+        assert_eq!(violations[0].path, "/spec/containers/1/resources/limits/memory");
+        assert_eq!(violations[1].path, "/spec");
+        assert!(violations[1].message.contains("1800m"));
+
+        let mut spec = req.object.as_ref().unwrap().data["spec"].clone();
+        spec["overhead"] = json!({});
+        let containers = get_containers(&spec);
+        assert_eq!(pod_total(&spec, &containers, "requests", "cpu"), Quantity::from_milli(1700));
+    }
+
+    #[test]
+    fn test_extended_resources() {
+        let config: ResourceLimitsPolicy = serde_yaml::from_str(
+            r#"
+enabled: true
+mode: enforce
+inject_defaults: true
+resources:
+  nvidia.com/gpu: {max: "2", required: true}
+  ephemeral-storage: {default_request: 1Gi, default_limit: 2Gi}
+"#,
+        )
+        .unwrap();
+        let policy = ResourceLimits::new(config, templates()).unwrap();
+        let req = request(
+            pod(
+                json!({}),
+                json!([{
+                    "name": "train",
+                    "resources": {
+                        "requests": {"cpu": "1", "memory": "1Gi"},
+                        "limits": {"cpu": "1", "memory": "1Gi", "nvidia.com/gpu": "4"},
+                    },
+                }]),
+            ),
+            json!({}),
+        );
+
+        // The GPU limit alone satisfies `required`: Kubernetes copies it
+        // into the request.
+        let violations = policy.evaluate(&req, false);
+        let found: Vec<_> = violations.iter().map(|v| (v.rule, v.path.as_str())).collect();
+        assert_eq!(
+            found,
+            vec![("resource_exceeds_max", "/spec/containers/0/resources/limits/nvidia.com~1gpu")]
+        );
+
+        let patches: Vec<_> = policy.mutate(&req).iter().map(|p| p.path().to_string()).collect();
+        assert_eq!(
+            patches,
+            vec![
+                "/spec/containers/0/resources/requests/ephemeral-storage",
+                "/spec/containers/0/resources/limits/ephemeral-storage",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_injected_defaults_stay_consistent() {
+        let config: ResourceLimitsPolicy = serde_yaml::from_str(
+            r#"
+enabled: true
+mode: enforce
+inject_defaults: true
+resources:
+  cpu: {default_request: 100m, default_limit: 500m}
+  memory: {default_request: 128Mi, default_limit: 256Mi}
+"#,
+        )
+        .unwrap();
+        let policy = ResourceLimits::new(config, templates()).unwrap();
+        let req = request(
+            pod(
+                json!({}),
+                json!([{
+                    "name": "app",
+                    "resources": {"requests": {"cpu": "2"}, "limits": {"memory": "64Mi"}},
+                }]),
+            ),
+            json!({}),
+        );
+
+        let mut patched = req.object.as_ref().unwrap().data.clone();
+        json_patch::patch(&mut patched, &policy.mutate(&req)).unwrap();
+        assert_eq!(
+            patched["spec"]["containers"][0]["resources"],
+            json!({
+                "requests": {"cpu": "2", "memory": "64Mi"},
+                "limits": {"cpu": "2", "memory": "64Mi"},
+            })
+        );
+    }
+
+    #[test]
+    fn test_null_section_replaced() {
+        let config: ResourceLimitsPolicy = serde_yaml::from_str(
+            "{enabled: true, mode: enforce, inject_defaults: true, \
+              resources: {cpu: {default_request: 100m, default_limit: 500m}}}",
+        )
+        .unwrap();
+        let policy = ResourceLimits::new(config, templates()).unwrap();
+        let req = request(
+            pod(
+                json!({}),
+                json!([{"name": "app", "resources": {"requests": null, "limits": {"cpu": "1"}}}]),
+            ),
+            json!({}),
+        );
+
+        let patches = policy.mutate(&req);
+        assert!(matches!(&patches[0], PatchOperation::Replace(_)));
+        let mut patched = req.object.as_ref().unwrap().data.clone();
+        json_patch::patch(&mut patched, &patches).unwrap();
+        assert_eq!(
+            patched["spec"]["containers"][0]["resources"],
+            json!({
+                "requests": {"cpu": "100m", "memory": "128Mi"},
+                "limits": {"cpu": "1", "memory": "512Mi"},
+            })
+        );
+    }
+
+    #[test]
+    fn test_min_request_and_request_within_limit() {
+        let config: ResourceLimitsPolicy = serde_yaml::from_str(
+            "{enabled: true, mode: enforce, resources: {cpu: {min_request: 50m}}}",
+        )
+        .unwrap();
+        let req = request(
+            pod(
+                json!({}),
+                json!([{
+                    "name": "app",
+                    "resources": {
+                        "requests": {"cpu": "10m", "memory": "1Gi"},
+                        "limits": {"cpu": "100m", "memory": "512Mi"},
+                    },
+                }]),
+            ),
+            json!({}),
+        );
+
+        let violations = ResourceLimits::new(config, templates()).unwrap().evaluate(&req, false);
+        let found: Vec<_> = violations.iter().map(|v| (v.rule, v.path.as_str())).collect();
+        assert_eq!(
+            found,
+            vec![
+                ("request_below_min", "/spec/containers/0/resources/requests/cpu"),
+                ("request_exceeds_limit", "/spec/containers/0/resources/requests/memory"),
+            ]
+        );
+        assert_eq!(
+            violations[1].message,
+            "container 'app' memory request '1Gi' is greater than its limit '512Mi'"
+        );
+    }
 
     #[test]
-    fn test_parse_cpu_millicores() {
-        assert_eq!(parse_cpu_millicores("100m"), Some(100));
-        assert_eq!(parse_cpu_millicores("1"), Some(1000));
-        assert_eq!(parse_cpu_millicores("0.5"), Some(500));
-        assert_eq!(parse_cpu_millicores("1.5"), Some(1500));
-        assert_eq!(parse_cpu_millicores("250m"), Some(250));
+    fn test_default_profiles() {
+        let config: ResourceLimitsPolicy = serde_yaml::from_str(
+            r#"
+enabled: true
+mode: enforce
+inject_defaults: true
+default_profiles:
+  - name: jvm
+    image: "*/java-*"
+    default_request: {memory: 1Gi}
+    default_limit: {memory: 2Gi}
+  - name: batch
+    namespaces: ["batch-*"]
+    container_name: "^worker-"
+    default_request: {cpu: "1"}
+"#,
+        )
+        .unwrap();
+        let policy = ResourceLimits::new(config, templates()).unwrap();
+        let req = request(
+            pod(
+                json!({}),
+                json!([
+                    {"name": "api", "image": "registry.corp/java-api:1.0"},
+                    {"name": "worker-1", "image": "registry.corp/worker:1.0"},
+                    {"name": "sidecar", "image": "envoy:1.30"},
+                ]),
+            ),
+            json!({"namespace": "batch-nightly"}),
+        );
+
+        let patches = policy.mutate(&req);
+        let requests: Vec<_> = patches
+            .iter()
+            .map(|p| match p {
+                PatchOperation::Add(op) => op.value["requests"].clone(),
+                _ => unreachable!(),
+            })
+            .collect();
+        assert_eq!(
+            requests,
+            vec![
+                json!({"cpu": "100m", "memory": "1Gi"}),
+                json!({"cpu": "1", "memory": "128Mi"}),
+                json!({"cpu": "100m", "memory": "128Mi"}),
+            ]
+        );
+        let PatchOperation::Add(op) = &patches[0] else {
+            unreachable!()
+        };
+        assert_eq!(op.value["limits"]["memory"], "2Gi");
+
+        let invalid: ResourceLimitsPolicy = serde_yaml::from_str(
+            "{enabled: true, mode: enforce, \
+             default_profiles: [{name: typo, container_name: \"worker-(\"}]}",
+        )
+        .unwrap();
+        assert!(matches!(
+            ResourceLimits::new(invalid, templates()),
+            Err(ConfigError::InvalidRegex { .. })
+        ));
     }
 
     #[test]
-    fn test_parse_memory_bytes() {
-        assert_eq!(parse_memory_bytes("128Mi"), Some(128 * 1024 * 1024));
-        assert_eq!(parse_memory_bytes("1Gi"), Some(1024 * 1024 * 1024));
-        assert_eq!(parse_memory_bytes("512Ki"), Some(512 * 1024));
-        assert_eq!(parse_memory_bytes("1000"), Some(1000));
-        assert_eq!(parse_memory_bytes("1G"), Some(1_000_000_000));
-        assert_eq!(parse_memory_bytes("500M"), Some(500_000_000));
+    fn test_invalid_and_numeric_quantities() {
+        let config: ResourceLimitsPolicy =
+            serde_yaml::from_str("{enabled: true, mode: enforce, max_cpu_millicores: 1000}")
+                .unwrap();
+        let mut object = pod(
+            json!({}),
+            json!([{
+                "name": "app",
+                "resources": {
+                    "requests": {"cpu": 0.5, "memory": "lots"},
+                    "limits": {"cpu": 2, "memory": "1Gi"},
+                },
+            }]),
+        );
+        object["spec"]["overhead"] = json!({"cpu": "1x"});
+
+        let violations =
+            ResourceLimits::new(config, templates()).unwrap().evaluate(&request(object, json!({})), false);
+        let found: Vec<_> = violations.iter().map(|v| (v.rule, v.path.as_str())).collect();
+        assert_eq!(
+            found,
+            vec![
+                ("cpu_exceeds_max", "/spec/containers/0/resources/limits/cpu"),
+                ("invalid_quantity", "/spec/containers/0/resources/requests/memory"),
+                ("invalid_quantity", "/spec/overhead/cpu"),
+            ]
+        );
+        assert_eq!(
+            violations[1].message,
+            "container 'app' requests memory: 'lots' is not a valid quantity"
+        );
+    }
+
+    #[test]
+    fn test_quantity_caps() {
+        // max_cpu wins over the legacy field; max_memory caps at 1.5Gi.
+        let config: ResourceLimitsPolicy = serde_yaml::from_str(
+            "{enabled: true, mode: enforce, max_cpu: 2500m, max_cpu_millicores: 1000, \
+             max_memory: 1.5Gi, max_pod_memory: 1Gi}",
+        )
+        .unwrap();
+        let resources = json!({
+            "requests": {"cpu": "2", "memory": "1Gi"},
+            "limits": {"cpu": "2", "memory": "2Gi"},
+        });
+        let object = pod(json!({}), json!([{"name": "app", "resources": resources}]));
+
+        let violations =
+            ResourceLimits::new(config, templates()).unwrap().evaluate(&request(object, json!({})), false);
+        let rules: Vec<_> = violations.iter().map(|v| v.rule).collect();
+        assert_eq!(rules, vec!["memory_exceeds_max", "pod_memory_exceeds_max"]);
+        assert_eq!(
+            violations[0].message,
+            "container 'app' limits memory '2Gi' exceeds maximum allowed '1536Mi'"
+        );
+
+        let invalid = "{enabled: true, mode: enforce, resources: {cpu: {max: 2 cores}}}";
+        assert!(serde_yaml::from_str::<ResourceLimitsPolicy>(invalid).is_err());
     }
 }
@@ -1,38 +1,113 @@
-use json_patch::jsonptr::PointerBuf;
 use json_patch::{AddOperation, PatchOperation};
 use kube::core::admission::AdmissionRequest;
 use kube::core::DynamicObject;
 use serde_json::{json, Value};
 
-use crate::config::TopologySpreadPolicy;
+use crate::config::{PolicyConfig, TopologySpreadPolicy};
 
-use super::{get_pod_spec, spec_prefix, PolicyOutput};
+use super::{field_path, field_pointer, PodTemplates, Policy, Violation};
 
-pub fn evaluate(
+pub const NAME: &str = "topology_spread";
+
+pub struct TopologySpread {
+    config: TopologySpreadPolicy,
+    templates: PodTemplates,
+}
+
+impl TopologySpread {
+    pub fn new(config: TopologySpreadPolicy, templates: PodTemplates) -> Self {
+        Self { config, templates }
+    }
+}
+
+impl Policy for TopologySpread {
+    fn name(&self) -> &'static str {
+        NAME
+    }
+
+    fn config(&self) -> &dyn PolicyConfig {
+        &self.config
+    }
+
+    fn evaluate(
+        &self,
+        request: &AdmissionRequest<DynamicObject>,
+        mutating: bool,
+    ) -> Vec<Violation> {
+        evaluate(&self.config, &self.templates, request, mutating)
+    }
+
+    fn mutate(&self, request: &AdmissionRequest<DynamicObject>) -> Vec<PatchOperation> {
+        if !self.config.inject_if_missing {
+            return Vec::new();
+        }
+
+        let object = match &request.object {
+            Some(obj) => obj,
+            None => return Vec::new(),
+        };
+
+        let paths = match self.templates.locate(request) {
+            Some(paths) => paths,
+            None => return Vec::new(),
+        };
+        let pod_spec = match paths.pod_spec(object) {
+            Some(spec) => spec,
+            None => return Vec::new(),
+        };
+
+        if has_constraints(pod_spec) {
+            return Vec::new();
+        }
+
+        let labels = paths.pod_labels(object);
+        if labels.as_object().is_none_or(|m| m.is_empty()) {
+            return Vec::new();
+        }
+
+        let constraint = json!([{
+            "maxSkew": self.config.max_skew,
+            "topologyKey": self.config.topology_key,
+            "whenUnsatisfiable": self.config.when_unsatisfiable,
+            "labelSelector": json!({ "matchLabels": labels }),
+        }]);
+
+        vec![PatchOperation::Add(AddOperation {
+            path: field_pointer(&paths.spec, &["topologySpreadConstraints"]),
+            value: constraint,
+        })]
+    }
+}
+
+fn evaluate(
     config: &TopologySpreadPolicy,
+    templates: &PodTemplates,
     request: &AdmissionRequest<DynamicObject>,
     mutating: bool,
-) -> PolicyOutput {
+) -> Vec<Violation> {
     let object = match &request.object {
         Some(obj) => obj,
-        None => return PolicyOutput::allowed(),
+        None => return Vec::new(),
     };
 
-    let kind = &request.kind.kind;
-    let pod_spec = match get_pod_spec(&object.data, kind) {
+    let paths = match templates.locate(request) {
+        Some(paths) => paths,
+        None => return Vec::new(),
+    };
+    let pod_spec = match paths.pod_spec(object) {
         Some(spec) => spec,
-        None => return PolicyOutput::allowed(),
+        None => return Vec::new(),
     };
 
+    let kind = &request.kind.kind;
     let resource_name = super::resource_name(request, object);
 
-    let prefix = spec_prefix(kind);
+    let prefix = &paths.spec;
     let constraints = pod_spec
         .get("topologySpreadConstraints")
         .and_then(|c| c.as_array());
 
     let mut violations = Vec::new();
-    let mut patches = Vec::new();
 
     match constraints {
         Some(constraints) if !constraints.is_empty() => {
@@ -43,13 +118,21 @@ pub fn evaluate(
                             .get("topologyKey")
                             .and_then(|v| v.as_str())
                             .unwrap_or("<unset>");
-                        violations.push(format!(
-                            "topologySpreadConstraints[{i}] on {} '{}' has maxSkew={} \
-                             (topologyKey='{topology_key}') exceeding maximum {}",
-                            kind,
-                            resource_name,
-                            max_skew,
-                            config.max_skew,
+                        violations.push(Violation::new(
+                            NAME,
+                            "max_skew_exceeded",
+                            field_path(
+                                prefix,
+                                &["topologySpreadConstraints", &i.to_string(), "maxSkew"],
+                            ),
+                            format!(
+                                "topologySpreadConstraints[{i}] on {} '{}' has maxSkew={} \
+                                 (topologyKey='{topology_key}') exceeding maximum {}",
+                                kind,
+                                resource_name,
+                                max_skew,
+                                config.max_skew,
+                            ),
                         ));
                     }
                 }
@@ -59,62 +142,43 @@ pub fn evaluate(
             // Skip violation in mutate path if inject_if_missing will fix it
             let will_be_patched = mutating && config.inject_if_missing;
             if !will_be_patched {
-                violations.push(format!(
-                    "{kind} '{resource_name}' has no topologySpreadConstraints"
-                ));
+                violations.push(
+                    Violation::new(
+                        NAME,
+                        "missing_constraints",
+                        field_path(prefix, &["topologySpreadConstraints"]),
+                        format!("{kind} '{resource_name}' has no topologySpreadConstraints"),
+                    )
+                    .with_hint(format!(
+                        "add a constraint on '{}' with maxSkew <= {}",
+                        config.topology_key, config.max_skew
+                    )),
+                );
             }
 
             if config.inject_if_missing {
-                let labels = get_pod_labels(object, kind);
+                let labels = paths.pod_labels(object);
                 if labels.as_object().is_none_or(|m| m.is_empty()) {
-                    violations.push(format!(
-                        "{kind} '{resource_name}' has no labels, cannot inject topologySpreadConstraints"
+                    violations.push(Violation::new(
+                        NAME,
+                        "missing_pod_labels",
+                        paths.labels.to_string(),
+                        format!(
+                            "{kind} '{resource_name}' has no labels, \
+                             cannot inject topologySpreadConstraints"
+                        ),
                     ));
-                    return PolicyOutput { violations, patches };
                 }
-
-                let constraint = json!([{
-                    "maxSkew": config.max_skew,
-                    "topologyKey": config.topology_key,
-                    "whenUnsatisfiable": config.when_unsatisfiable,
-                    "labelSelector": json!({ "matchLabels": labels }),
-                }]);
-
-                let mut path_parts: Vec<&str> = prefix.split('/').collect();
-                path_parts.push("topologySpreadConstraints");
-                patches.push(PatchOperation::Add(AddOperation {
-                    path: PointerBuf::from_tokens(path_parts),
-                    value: constraint,
-                }));
             }
         }
     }
 
-    PolicyOutput {
-        violations,
-        patches,
-    }
+    violations
 }
 
-fn get_pod_labels(object: &DynamicObject, kind: &str) -> Value {
-    match kind {
-        "Pod" => match &object.metadata.labels {
-            Some(labels) if !labels.is_empty() => {
-                let map: serde_json::Map<String, Value> = labels
-                    .iter()
-                    .map(|(k, v)| (k.clone(), Value::String(v.clone())))
-                    .collect();
-                Value::Object(map)
-            }
-            _ => json!({}),
-        },
-        _ => object
-            .data
-            .get("spec")
-            .and_then(|s| s.get("template"))
-            .and_then(|t| t.get("metadata"))
-            .and_then(|m| m.get("labels"))
-            .cloned()
-            .unwrap_or(json!({})),
-    }
+fn has_constraints(pod_spec: &Value) -> bool {
+    pod_spec
+        .get("topologySpreadConstraints")
+        .and_then(|c| c.as_array())
+        .is_some_and(|c| !c.is_empty())
 }
@@ -0,0 +1,350 @@
+//! Kubernetes resource quantities (`500m`, `1.5Gi`, `2e3`, ...).
+
+use std::fmt;
+use std::iter::Sum;
+use std::ops::Add;
+use std::str::FromStr;
+
+use serde::de::{self, Deserializer, Visitor};
+use serde::{Deserialize, Serialize, Serializer};
+use serde_json::Value;
+use thiserror::Error;
+
+const NANOS_PER_UNIT: i128 = 1_000_000_000;
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum QuantityError {
+    #[error("quantity is empty")]
+    Empty,
+    #[error("'{0}' is not a valid quantity")]
+    Invalid(String),
+    #[error("quantity '{0}' is out of range")]
+    OutOfRange(String),
+    #[error("quantity must be a string or a number, got {0}")]
+    NotAQuantity(Value),
+}
+
+/// An exact quantity, stored in nano units. As in Kubernetes, precision below
+/// `1n` is rounded up.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Quantity {
+    nanos: i128,
+}
+
+impl Quantity {
+    pub const ZERO: Quantity = Quantity { nanos: 0 };
+
+    pub fn from_units(units: u64) -> Self {
+        Self {
+            nanos: units as i128 * NANOS_PER_UNIT,
+        }
+    }
+
+    pub fn from_milli(milli: u64) -> Self {
+        Self {
+            nanos: milli as i128 * 1_000_000,
+        }
+    }
+
+    /// Reads a quantity from a JSON string or number.
+    pub fn from_json(value: &Value) -> Result<Self, QuantityError> {
+        match value {
+            Value::String(s) => s.parse(),
+            Value::Number(n) => n.to_string().parse(),
+            other => Err(QuantityError::NotAQuantity(other.clone())),
+        }
+    }
+
+    pub fn as_f64(self) -> f64 {
+        self.nanos as f64 / NANOS_PER_UNIT as f64
+    }
+
+    pub fn is_zero(self) -> bool {
+        self.nanos == 0
+    }
+}
+
+fn div_ceil(a: i128, b: i128) -> i128 {
+    let q = a / b;
+    if a % b > 0 { q + 1 } else { q }
+}
+
+/// Splits `s` into the signed number and its suffix.
+fn split_number(s: &str) -> (&str, &str) {
+    let bytes = s.as_bytes();
+    let mut end = 0;
+    if matches!(bytes.first(), Some(b'+' | b'-')) {
+        end = 1;
+    }
+    while end < bytes.len() && (bytes[end].is_ascii_digit() || bytes[end] == b'.') {
+        end += 1;
+    }
+    s.split_at(end)
+}
+
+enum Suffix {
+    /// Multiply by 2^n.
+    Binary(u32),
+    /// Multiply by 10^n.
+    Decimal(i32),
+}
+
+fn parse_suffix(suffix: &str) -> Option<Suffix> {
+    let binary = |n| Some(Suffix::Binary(n));
+    let decimal = |n| Some(Suffix::Decimal(n));
+    match suffix {
+        "Ki" => binary(10),
+        "Mi" => binary(20),
+        "Gi" => binary(30),
+        "Ti" => binary(40),
+        "Pi" => binary(50),
+        "Ei" => binary(60),
+        "n" => decimal(-9),
+        "u" => decimal(-6),
+        "m" => decimal(-3),
+        "" => decimal(0),
+        "k" => decimal(3),
+        "M" => decimal(6),
+        "G" => decimal(9),
+        "T" => decimal(12),
+        "P" => decimal(15),
+        "E" => decimal(18),
+        _ => {
+            let exponent = suffix.strip_prefix(['e', 'E'])?;
+            let digits = exponent.strip_prefix(['+', '-']).unwrap_or(exponent);
+            if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+                return None;
+            }
+            exponent.parse().ok().map(Suffix::Decimal)
+        }
+    }
+}
+
+impl FromStr for Quantity {
+    type Err = QuantityError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || QuantityError::Invalid(s.to_string());
+        let out_of_range = || QuantityError::OutOfRange(s.to_string());
+
+        if s.is_empty() {
+            return Err(QuantityError::Empty);
+        }
+
+        let (number, suffix) = split_number(s);
+        let (negative, number) = match number.as_bytes().first() {
+            Some(b'-') => (true, &number[1..]),
+            Some(b'+') => (false, &number[1..]),
+            _ => (false, number),
+        };
+        let (whole, fraction) = number.split_once('.').unwrap_or((number, ""));
+        if (whole.is_empty() && fraction.is_empty()) || fraction.contains('.') {
+            return Err(invalid());
+        }
+        let suffix = parse_suffix(suffix).ok_or_else(invalid)?;
+
+        // The value is mantissa * 10^exponent units, kept exact as integers.
+        let digits: String = whole.chars().chain(fraction.chars()).collect();
+        let digits = digits.trim_start_matches('0');
+        if digits.len() > 36 {
+            return Err(out_of_range());
+        }
+        if digits.is_empty() {
+            return Ok(Quantity::ZERO);
+        }
+        let mantissa: i128 = digits.parse().map_err(|_| invalid())?;
+        let mut exponent = -(fraction.len() as i32);
+
+        let mut mantissa = match suffix {
+            Suffix::Binary(n) => mantissa.checked_mul(1 << n).ok_or_else(out_of_range)?,
+            Suffix::Decimal(n) => {
+                exponent = exponent.checked_add(n).ok_or_else(out_of_range)?;
+                mantissa
+            }
+        };
+
+        // Convert to nanos: multiply by 10^(exponent + 9), rounding up.
+        let shift = exponent + 9;
+        if shift >= 0 {
+            let factor = 10i128.checked_pow(shift as u32).ok_or_else(out_of_range)?;
+            mantissa = mantissa.checked_mul(factor).ok_or_else(out_of_range)?;
+        } else {
+            mantissa = match 10i128.checked_pow(shift.unsigned_abs()) {
+                Some(divisor) => div_ceil(mantissa, divisor),
+                // Anything this small rounds up to 1n.
+                None => 1,
+            };
+        }
+
+        Ok(Quantity {
+            nanos: if negative { -mantissa } else { mantissa },
+        })
+    }
+}
+
+impl fmt::Display for Quantity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let nanos = self.nanos;
+        if nanos % NANOS_PER_UNIT != 0 {
+            for (divisor, suffix) in [(1_000_000, "m"), (1_000, "u")] {
+                if nanos % divisor == 0 {
+                    return write!(f, "{}{suffix}", nanos / divisor);
+                }
+            }
+            return write!(f, "{nanos}n");
+        }
+
+        let units = nanos / NANOS_PER_UNIT;
+        if units == 0 {
+            return write!(f, "0");
+        }
+        let binary = [(60, "Ei"), (50, "Pi"), (40, "Ti"), (30, "Gi"), (20, "Mi"), (10, "Ki")];
+        for (shift, suffix) in binary {
+            if units % (1i128 << shift) == 0 {
+                return write!(f, "{}{suffix}", units >> shift);
+            }
+        }
+        let decimal = [(18, "E"), (15, "P"), (12, "T"), (9, "G"), (6, "M"), (3, "k")];
+        for (exp, suffix) in decimal {
+            let factor = 10i128.pow(exp);
+            if units % factor == 0 {
+                return write!(f, "{}{suffix}", units / factor);
+            }
+        }
+        write!(f, "{units}")
+    }
+}
+
+impl Add for Quantity {
+    type Output = Quantity;
+
+    fn add(self, other: Quantity) -> Quantity {
+        Quantity {
+            nanos: self.nanos.saturating_add(other.nanos),
+        }
+    }
+}
+
+impl Sum for Quantity {
+    fn sum<I: Iterator<Item = Quantity>>(iter: I) -> Quantity {
+        iter.fold(Quantity::ZERO, Add::add)
+    }
+}
+
+/// Serialized in canonical form, e.g. `1536Mi`.
+impl Serialize for Quantity {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// Accepts a quantity string or a plain number, as in Kubernetes manifests.
+impl<'de> Deserialize<'de> for Quantity {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct QuantityVisitor;
+
+        impl Visitor<'_> for QuantityVisitor {
+            type Value = Quantity;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str("a Kubernetes quantity such as 500m, 2 or 1Gi")
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<Quantity, E> {
+                v.parse().map_err(E::custom)
+            }
+
+            fn visit_u64<E: de::Error>(self, v: u64) -> Result<Quantity, E> {
+                self.visit_str(&v.to_string())
+            }
+
+            fn visit_i64<E: de::Error>(self, v: i64) -> Result<Quantity, E> {
+                self.visit_str(&v.to_string())
+            }
+
+            fn visit_f64<E: de::Error>(self, v: f64) -> Result<Quantity, E> {
+                self.visit_str(&v.to_string())
+            }
+        }
+
+        deserializer.deserialize_any(QuantityVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn q(s: &str) -> Quantity {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_parse() {
+        let milli = Quantity::from_milli;
+        let units = Quantity::from_units;
+        assert_eq!(q("100m"), milli(100));
+        assert_eq!(q("1"), milli(1000));
+        assert_eq!(q("0.5"), milli(500));
+        assert_eq!(q("1.5"), milli(1500));
+        assert_eq!(q("128Mi"), units(128 * 1024 * 1024));
+        assert_eq!(q("1Gi"), units(1024 * 1024 * 1024));
+        assert_eq!(q("1.5Gi"), units(1536 * 1024 * 1024));
+        assert_eq!(q("512Ki"), units(512 * 1024));
+        assert_eq!(q("1G"), units(1_000_000_000));
+        assert_eq!(q("500M"), units(500_000_000));
+        assert_eq!(q("2e3"), units(2000));
+        assert_eq!(q("12E-1"), milli(1200));
+        assert_eq!(q("1E"), units(1_000_000_000_000_000_000));
+        assert_eq!(q(".5"), milli(500));
+        assert_eq!(q("+1"), units(1));
+        assert_eq!(q("-1m") + q("1m"), Quantity::ZERO);
+        assert_eq!(q("250u"), q("0.25m"));
+        assert_eq!(q("1Ki"), q("1024"));
+        assert!(q("999m") < q("1"));
+    }
+
+    #[test]
+    fn test_rounding() {
+        // Sub-nano precision rounds up, like Kubernetes.
+        assert_eq!(q("1.5n").to_string(), "2n");
+        assert_eq!(q("1e-12").to_string(), "1n");
+        assert_eq!(q("0.0001m").to_string(), "100n");
+        assert_eq!(q("1.0000001m").to_string(), "1000001n");
+    }
+
+    #[test]
+    fn test_invalid() {
+        for s in ["", "abc", "1.2.3", "1Kb", "m", "1e", "1e+", "1 Gi", "--1", "1ee3"] {
+            assert!(s.parse::<Quantity>().is_err(), "{s:?} should not parse");
+        }
+        assert!(matches!("1e100".parse::<Quantity>(), Err(QuantityError::OutOfRange(_))));
+        assert!(Quantity::from_json(&json!(true)).is_err());
+    }
+
+    #[test]
+    fn test_json_numbers() {
+        assert_eq!(Quantity::from_json(&json!(2)).unwrap(), q("2"));
+        assert_eq!(Quantity::from_json(&json!(0.5)).unwrap(), q("500m"));
+        assert_eq!(Quantity::from_json(&json!("1Gi")).unwrap(), q("1Gi"));
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(q("1800m").to_string(), "1800m");
+        assert_eq!(q("2000m").to_string(), "2");
+        assert_eq!(q("16384Mi").to_string(), "16Gi");
+        assert_eq!(q("2000").to_string(), "2k");
+        assert_eq!(q("100").to_string(), "100");
+        assert_eq!((q("1Gi") + q("512Mi")).to_string(), "1536Mi");
+    }
+
+    #[test]
+    fn test_serde() {
+        let parsed: Vec<Quantity> = serde_yaml::from_str("[1.5Gi, 2500m, 2, 0.5]").unwrap();
+        assert_eq!(parsed, [q("1536Mi"), q("2.5"), q("2"), q("500m")]);
+        assert_eq!(serde_json::to_value(q("1.5Gi")).unwrap(), json!("1536Mi"));
+        assert!(serde_yaml::from_str::<Quantity>("1.5GB").is_err());
+    }
+}
@@ -0,0 +1,189 @@
+use std::fs;
+use std::time::Duration;
+
+use tokio::sync::watch;
+use tracing::{error, info, warn};
+
+use crate::config::SentinelConfig;
+use crate::engine::PolicyEngine;
+use crate::handlers::SharedState;
+use crate::metrics::ReloadLabels;
+
+/// Hex FNV-1a hash of the config file contents, used to detect changes and
+/// reported in `sentinel_config_info`.
+pub fn config_hash(contents: &[u8]) -> String {
+    let hash = contents
+        .iter()
+        .fold(0xcbf2_9ce4_8422_2325_u64, |hash, &b| {
+            (hash ^ u64::from(b)).wrapping_mul(0x0100_0000_01b3)
+        });
+    format!("{hash:016x}")
+}
+
+/// Polls the config file and swaps in a rebuilt `PolicyEngine` whenever its
+/// contents change.
+///
+/// Polling content rather than watching inodes covers ConfigMap volumes,
+/// where the kubelet swaps a `..data` symlink instead of writing the file.
+/// Invalid configs are logged and counted, and the previous engine stays
+/// active until a valid config shows up.
+pub async fn watch_config(
+    path: String,
+    initial_hash: String,
+    interval: Duration,
+    state: SharedState,
+    mut shutdown_rx: watch::Receiver<()>,
+) {
+    let mut ticker = tokio::time::interval(interval);
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    let mut last_hash = initial_hash;
+
+    info!(%path, interval_secs = interval.as_secs(), "watching config for changes");
+
+    loop {
+        tokio::select! {
+            _ = ticker.tick() => {}
+            _ = shutdown_rx.changed() => return,
+        }
+
+        // Reading the file and compiling the policies block, so keep them off
+        // the runtime threads serving admission requests.
+        let (poll_path, previous) = (path.clone(), last_hash.clone());
+        let polled = tokio::task::spawn_blocking(move || poll(&poll_path, &previous)).await;
+        let (hash, result) = match polled {
+            Ok(Poll::Unchanged) => continue,
+            Ok(Poll::Unreadable(e)) => {
+                warn!(%path, "failed to read config for reload: {e}");
+                continue;
+            }
+            Ok(Poll::Changed { hash, engine }) => (hash, engine),
+            Err(e) => {
+                error!(%path, "config reload task failed: {e}");
+                continue;
+            }
+        };
+
+        match result {
+            Ok(engine) => {
+                state.metrics.set_policies_enabled(engine.policies());
+                state.swap_engine(engine);
+                state.metrics.set_config_hash(hash.clone());
+                record_reload(&state, "success");
+                info!(%path, config_hash = %hash, "config reloaded");
+            }
+            Err(e) => {
+                record_reload(&state, "failure");
+                error!(%path, config_hash = %hash, "config reload rejected, keeping previous config: {e}");
+            }
+        }
+
+        // Remember rejected configs too, so a broken file is reported once
+        // rather than on every tick.
+        last_hash = hash;
+    }
+}
+
+enum Poll {
+    Unchanged,
+    Unreadable(std::io::Error),
+    Changed {
+        hash: String,
+        engine: Result<PolicyEngine, String>,
+    },
+}
+
+/// Reads the config file and, if its hash differs from `last_hash`, builds
+/// an engine from it.
+fn poll(path: &str, last_hash: &str) -> Poll {
+    let contents = match fs::read(path) {
+        Ok(contents) => contents,
+        Err(e) => return Poll::Unreadable(e),
+    };
+    let hash = config_hash(&contents);
+    if hash == last_hash {
+        return Poll::Unchanged;
+    }
+
+    let engine = SentinelConfig::load(path)
+        .map_err(|e| e.to_string())
+        .and_then(|config| PolicyEngine::new(config.policies).map_err(|e| e.to_string()));
+    Poll::Changed { hash, engine }
+}
+
+fn record_reload(state: &SharedState, result: &'static str) {
+    state
+        .metrics
+        .config_reloads_total
+        .get_or_create(&ReloadLabels { result })
+        .inc();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handlers::AppState;
+    use crate::metrics::SentinelMetrics;
+    use prometheus_client::registry::Registry;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_config_hash() {
+        assert_eq!(config_hash(b""), "cbf29ce484222325");
+        assert_eq!(config_hash(b"a"), "af63dc4c8601ec8c");
+        assert_ne!(config_hash(b"mode: warn"), config_hash(b"mode: enforce"));
+    }
+
+    /// Waits up to two seconds for `condition` to hold.
+    async fn eventually(condition: impl Fn() -> bool) -> bool {
+        for _ in 0..200 {
+            if condition() {
+                return true;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        false
+    }
+
+    #[tokio::test]
+    async fn test_watch_config_swaps_engine() {
+        let file = format!("sentinel-reload-{}.yaml", std::process::id());
+        let path = std::env::temp_dir().join(file);
+        let path_str = path.to_str().unwrap().to_string();
+        let original = include_str!("../config/policies.yaml");
+        fs::write(&path, original).unwrap();
+
+        let config = SentinelConfig::load(&path_str).unwrap();
+        let engine = PolicyEngine::new(config.policies).unwrap();
+        let metrics = SentinelMetrics::new(&mut Registry::default(), engine.policies());
+        let state: SharedState = Arc::new(AppState::new(engine, metrics));
+        let failures = || {
+            state
+                .metrics
+                .config_reloads_total
+                .get_or_create(&ReloadLabels { result: "failure" })
+                .get()
+        };
+
+        let (shutdown_tx, shutdown_rx) = watch::channel(());
+        let watcher = tokio::spawn(watch_config(
+            path_str.clone(),
+            config_hash(original.as_bytes()),
+            Duration::from_millis(10),
+            state.clone(),
+            shutdown_rx,
+        ));
+
+        let initial = state.engine();
+        fs::write(&path, original.replacen("mode: enforce", "mode: warn", 1)).unwrap();
+        assert!(eventually(|| !Arc::ptr_eq(&initial, &state.engine())).await);
+        let reloaded = state.engine();
+
+        fs::write(&path, "policies: [not, a, map]\n").unwrap();
+        assert!(eventually(|| failures() == 1).await);
+        assert!(Arc::ptr_eq(&reloaded, &state.engine()));
+
+        shutdown_tx.send(()).unwrap();
+        watcher.await.unwrap();
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,184 @@
+use std::collections::BTreeMap;
+
+use kube::core::admission::AdmissionRequest;
+use kube::core::DynamicObject;
+
+use crate::config::{ExcludeRules, LabelSelector, SelectorOperator};
+
+const SERVICE_ACCOUNT_PREFIX: &str = "system:serviceaccount:";
+
+/// Shell-style glob match where `*` matches any run of characters and `?`
+/// matches exactly one.
+pub fn glob_match(pattern: &str, value: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let value: Vec<char> = value.chars().collect();
+
+    let (mut p, mut v) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while v < value.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, v));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == value[v] => {
+                p += 1;
+                v += 1;
+            }
+            _ => match backtrack {
+                Some((star_p, star_v)) => {
+                    p = star_p + 1;
+                    v = star_v + 1;
+                    backtrack = Some((star_p, star_v + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+impl LabelSelector {
+    pub fn is_empty(&self) -> bool {
+        self.match_labels.is_empty() && self.match_expressions.is_empty()
+    }
+
+    /// Kubernetes label selector semantics: every `match_labels` entry and
+    /// every expression must hold.
+    pub fn matches(&self, labels: &BTreeMap<String, String>) -> bool {
+        let labels_match = self
+            .match_labels
+            .iter()
+            .all(|(k, v)| labels.get(k) == Some(v));
+
+        labels_match
+            && self.match_expressions.iter().all(|expr| {
+                let value = labels.get(&expr.key);
+                match expr.operator {
+                    SelectorOperator::In => value.is_some_and(|v| expr.values.contains(v)),
+                    SelectorOperator::NotIn => value.is_none_or(|v| !expr.values.contains(v)),
+                    SelectorOperator::Exists => value.is_some(),
+                    SelectorOperator::DoesNotExist => value.is_none(),
+                }
+            })
+    }
+}
+
+impl ExcludeRules {
+    /// Returns which criterion exempts the request, if any.
+    pub fn matches(&self, request: &AdmissionRequest<DynamicObject>) -> Option<&'static str> {
+        if let Some(ns) = request.namespace.as_deref() {
+            if self.namespaces.iter().any(|pattern| glob_match(pattern, ns)) {
+                return Some("namespace");
+            }
+        }
+
+        if !self.object_labels.is_empty() {
+            let labels = request
+                .object
+                .as_ref()
+                .or(request.old_object.as_ref())
+                .and_then(|obj| obj.metadata.labels.as_ref());
+            if labels.is_some_and(|l| self.object_labels.matches(l)) {
+                return Some("object_labels");
+            }
+        }
+
+        let username = request.user_info.username.as_deref().unwrap_or_default();
+        if self.users.iter().any(|u| u == username) {
+            return Some("user");
+        }
+
+        let groups = request.user_info.groups.as_deref().unwrap_or_default();
+        if groups.iter().any(|g| self.groups.contains(g)) {
+            return Some("group");
+        }
+
+        if let Some(sa) = service_account(username) {
+            if self.service_accounts.iter().any(|pattern| glob_match(pattern, &sa)) {
+                return Some("service_account");
+            }
+        }
+
+        None
+    }
+}
+
+/// Converts `system:serviceaccount:<ns>:<name>` into `<ns>/<name>`.
+fn service_account(username: &str) -> Option<String> {
+    let (ns, name) = username.strip_prefix(SERVICE_ACCOUNT_PREFIX)?.split_once(':')?;
+    Some(format!("{ns}/{name}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::LabelSelectorRequirement;
+    use crate::testing;
+    use serde_json::json;
+
+    fn request(namespace: &str, username: &str, groups: &[&str]) -> AdmissionRequest<DynamicObject> {
+        testing::request(
+            testing::pod(json!({"tier": "system"}), json!([])),
+            json!({
+                "namespace": namespace,
+                "userInfo": {"username": username, "groups": groups},
+            }),
+        )
+    }
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("kube-*", "kube-system"));
+        assert!(glob_match("*", ""));
+        assert!(glob_match("team-?", "team-a"));
+        assert!(glob_match("*-prod-*", "payments-prod-eu"));
+        assert!(!glob_match("kube-*", "default"));
+        assert!(!glob_match("team-?", "team-ab"));
+        assert!(!glob_match("sentinel", "sentinel-dev"));
+    }
+
+    #[test]
+    fn test_label_selector() {
+        let labels = BTreeMap::from([("tier".to_string(), "system".to_string())]);
+        let selector = LabelSelector {
+            match_labels: BTreeMap::new(),
+            match_expressions: vec![LabelSelectorRequirement {
+                key: "tier".to_string(),
+                operator: SelectorOperator::In,
+                values: vec!["system".to_string(), "infra".to_string()],
+            }],
+        };
+        assert!(selector.matches(&labels));
+
+        let selector = LabelSelector {
+            match_labels: BTreeMap::from([("tier".to_string(), "app".to_string())]),
+            match_expressions: vec![],
+        };
+        assert!(!selector.matches(&labels));
+    }
+
+    #[test]
+    fn test_exclude_rules() {
+        let rules = ExcludeRules {
+            namespaces: vec!["kube-*".to_string()],
+            groups: vec!["system:masters".to_string()],
+            service_accounts: vec!["argocd/*".to_string()],
+            ..Default::default()
+        };
+
+        assert_eq!(rules.matches(&request("kube-system", "alice", &[])), Some("namespace"));
+        assert_eq!(
+            rules.matches(&request("default", "alice", &["system:masters"])),
+            Some("group")
+        );
+        assert_eq!(
+            rules.matches(&request("default", "system:serviceaccount:argocd:controller", &[])),
+            Some("service_account")
+        );
+        assert_eq!(rules.matches(&request("default", "alice", &["dev"])), None);
+        assert_eq!(ExcludeRules::default().matches(&request("kube-system", "alice", &[])), None);
+    }
+}
@@ -0,0 +1,646 @@
+//! Cosign-style image signature verification against an OCI registry.
+//!
+//! Cosign stores the signatures of `repo@sha256:<hex>` as the layers of the
+//! manifest tagged `sha256-<hex>.sig` in the same repository. Each layer is a
+//! simple-signing JSON payload naming the signed digest, with its base64
+//! ECDSA signature in the `dev.cosignproject.cosign/signature` annotation.
+
+use std::collections::HashMap;
+use std::sync::{Arc, LazyLock, Mutex, OnceLock, PoisonError};
+use std::time::{Duration, Instant};
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use http_body_util::{BodyExt, Empty, Limited};
+use hyper::body::Bytes;
+use hyper::header::{ACCEPT, AUTHORIZATION, LOCATION, WWW_AUTHENTICATE};
+use hyper::{Request, StatusCode, Uri};
+use hyper_rustls::HttpsConnector;
+use hyper_util::client::legacy::connect::HttpConnector;
+use hyper_util::client::legacy::Client;
+use hyper_util::rt::TokioExecutor;
+use ring::digest::{digest, SHA256};
+use ring::signature::{UnparsedPublicKey, ECDSA_P256_SHA256_ASN1};
+use serde_json::Value;
+use thiserror::Error;
+use tokio::runtime::{Handle, RuntimeFlavor};
+use tokio::task::JoinSet;
+use tracing::warn;
+
+const SIGNATURE_ANNOTATION: &str = "dev.cosignproject.cosign/signature";
+const MANIFEST_ACCEPT: &str =
+    "application/vnd.oci.image.manifest.v1+json, application/vnd.docker.distribution.manifest.v2+json";
+/// DER prefix of a SubjectPublicKeyInfo holding an uncompressed P-256 point.
+const P256_SPKI_PREFIX: &[u8] = &[
+    0x30, 0x59, 0x30, 0x13, 0x06, 0x07, 0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02, 0x01, 0x06, 0x08,
+    0x2a, 0x86, 0x48, 0xce, 0x3d, 0x03, 0x01, 0x07, 0x03, 0x42, 0x00,
+];
+const MAX_REDIRECTS: usize = 3;
+/// Manifests and signature payloads are small; anything larger is refused.
+const MAX_BODY_BYTES: usize = 4 * 1024 * 1024;
+
+type HttpsClient = Client<HttpsConnector<HttpConnector>, Empty<Bytes>>;
+
+/// Results by key set and digest, shared by every verifier so that overrides
+/// and reloaded configs do not check the same images again. Entries hold
+/// their expiry, as verifiers may use different TTLs.
+static CACHE: LazyLock<Mutex<HashMap<String, (Verification, Instant)>>> =
+    LazyLock::new(Mutex::default);
+
+#[derive(Debug, Error)]
+pub enum KeyError {
+    #[error("public key is not PEM: {0}")]
+    Pem(String),
+    #[error("public key is not an ECDSA P-256 key")]
+    UnsupportedKey,
+}
+
+#[derive(Debug, Clone, Error)]
+pub enum VerifyError {
+    #[error("registry request to {url} failed: {reason}")]
+    Http { url: String, reason: String },
+    #[error("registry returned {status} for {url}")]
+    Status { url: String, status: StatusCode },
+    #[error("invalid signature manifest: {0}")]
+    InvalidManifest(String),
+    #[error("timed out after {0:?}")]
+    Timeout(Duration),
+    #[error("signature verification needs a multi-threaded tokio runtime")]
+    NoRuntime,
+}
+
+/// The outcome of checking one digest, cached by [`SignatureVerifier`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Verification {
+    Verified,
+    /// No signature manifest exists for the digest.
+    Unsigned,
+    /// Signatures exist, but none verifies against the configured keys.
+    Invalid(String),
+}
+
+pub struct SignatureVerifier {
+    keys: Vec<Vec<u8>>,
+    /// Identifies `keys` in the shared cache.
+    key_id: String,
+    insecure_registries: Vec<String>,
+    timeout: Duration,
+    cache_ttl: Duration,
+}
+
+/// The HTTPS client shared by all verifiers, so its connection pool outlives
+/// config reloads.
+fn client() -> &'static HttpsClient {
+    static CLIENT: OnceLock<HttpsClient> = OnceLock::new();
+    CLIENT.get_or_init(|| {
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let builder = hyper_rustls::HttpsConnectorBuilder::new();
+        let builder = match builder.with_provider_and_native_roots(provider.clone()) {
+            Ok(builder) => builder,
+            Err(e) => {
+                warn!("no native root certificates, HTTPS registries will fail: {e}");
+                let config = rustls::ClientConfig::builder_with_provider(provider)
+                    .with_safe_default_protocol_versions()
+                    .expect("ring supports the default protocol versions")
+                    .with_root_certificates(rustls::RootCertStore::empty())
+                    .with_no_client_auth();
+                hyper_rustls::HttpsConnectorBuilder::new().with_tls_config(config)
+            }
+        };
+        let connector = builder.https_or_http().enable_http1().build();
+        Client::builder(TokioExecutor::new()).build(connector)
+    })
+}
+
+impl SignatureVerifier {
+    /// `keys` are uncompressed P-256 points, as returned by [`parse_public_key`].
+    /// `timeout` bounds a whole [`verify_all_blocking`](Self::verify_all_blocking) call.
+    pub fn new(
+        keys: Vec<Vec<u8>>,
+        insecure_registries: Vec<String>,
+        timeout: Duration,
+        cache_ttl: Duration,
+    ) -> Self {
+        let mut sorted = keys.clone();
+        sorted.sort();
+        let key_id = hex(digest(&SHA256, &sorted.concat()).as_ref());
+        Self {
+            keys,
+            key_id,
+            insecure_registries,
+            timeout,
+            cache_ttl,
+        }
+    }
+
+    /// Verifies `(repository, digest)` pairs concurrently from a synchronous
+    /// caller running on a multi-threaded tokio runtime, such as a policy
+    /// evaluation. Lookups still running when the timeout expires fail with
+    /// [`VerifyError::Timeout`]. Results are in the order of `images`.
+    pub fn verify_all_blocking(
+        self: &Arc<Self>,
+        images: Vec<(String, String)>,
+    ) -> Vec<Result<Verification, VerifyError>> {
+        let handle = match Handle::try_current() {
+            Ok(handle) if handle.runtime_flavor() == RuntimeFlavor::MultiThread => handle,
+            _ => return images.iter().map(|_| Err(VerifyError::NoRuntime)).collect(),
+        };
+        let deadline = tokio::time::Instant::now() + self.timeout;
+        let count = images.len();
+
+        tokio::task::block_in_place(|| {
+            handle.block_on(async {
+                let mut lookups = JoinSet::new();
+                for (i, (repository, digest)) in images.into_iter().enumerate() {
+                    let verifier = Arc::clone(self);
+                    lookups.spawn(async move {
+                        let lookup = verifier.verify(&repository, &digest);
+                        let result = tokio::time::timeout_at(deadline, lookup)
+                            .await
+                            .unwrap_or(Err(VerifyError::Timeout(verifier.timeout)));
+                        (i, result)
+                    });
+                }
+
+                let mut results: Vec<_> = (0..count).map(|_| Err(VerifyError::NoRuntime)).collect();
+                while let Some(joined) = lookups.join_next().await {
+                    if let Ok((i, result)) = joined {
+                        results[i] = result;
+                    }
+                }
+                results
+            })
+        })
+    }
+
+    /// Checks the signatures of `repository@digest`, where `repository`
+    /// includes the registry host (`gcr.io/project/app`). Results are cached
+    /// by key set and digest; errors are not.
+    pub async fn verify(&self, repository: &str, digest: &str) -> Result<Verification, VerifyError> {
+        let key = self.cache_key(digest);
+        let cached = CACHE.lock().unwrap_or_else(PoisonError::into_inner).get(&key).cloned();
+        if let Some((result, expires)) = cached {
+            if Instant::now() < expires {
+                return Ok(result);
+            }
+        }
+
+        let result = self.fetch_and_verify(repository, digest).await?;
+
+        let now = Instant::now();
+        let mut cache = CACHE.lock().unwrap_or_else(PoisonError::into_inner);
+        cache.retain(|_, (_, expires)| now < *expires);
+        cache.insert(key, (result.clone(), now + self.cache_ttl));
+        Ok(result)
+    }
+
+    fn cache_key(&self, digest: &str) -> String {
+        format!("{}/{digest}", self.key_id)
+    }
+
+    async fn fetch_and_verify(
+        &self,
+        repository: &str,
+        digest: &str,
+    ) -> Result<Verification, VerifyError> {
+        let (host, name) = repository.split_once('/').unwrap_or((repository, ""));
+        let host = if host == "docker.io" { "registry-1.docker.io" } else { host };
+        let scheme = if self.insecure_registries.iter().any(|r| r == host) {
+            "http"
+        } else {
+            "https"
+        };
+        let base = format!("{scheme}://{host}/v2/{name}");
+        let mut token = None;
+
+        let tag = digest.replacen(':', "-", 1) + ".sig";
+        let manifest_url = format!("{base}/manifests/{tag}");
+        let manifest = match self.get(&manifest_url, MANIFEST_ACCEPT, &mut token).await {
+            Ok(body) => body,
+            Err(VerifyError::Status { status, .. }) if status == StatusCode::NOT_FOUND => {
+                return Ok(Verification::Unsigned);
+            }
+            Err(e) => return Err(e),
+        };
+        let manifest: Value = serde_json::from_slice(&manifest)
+            .map_err(|e| VerifyError::InvalidManifest(e.to_string()))?;
+        let layers = manifest["layers"]
+            .as_array()
+            .ok_or_else(|| VerifyError::InvalidManifest("no layers".to_string()))?;
+
+        let mut reason = "signature manifest has no signed layers".to_string();
+        for layer in layers {
+            let (Some(layer_digest), Some(signature)) = (
+                layer["digest"].as_str(),
+                layer["annotations"][SIGNATURE_ANNOTATION].as_str(),
+            ) else {
+                continue;
+            };
+            let blob_url = format!("{base}/blobs/{layer_digest}");
+            let payload = self.get(&blob_url, "*/*", &mut token).await?;
+            match self.check_layer(digest, layer_digest, &payload, signature) {
+                Ok(()) => return Ok(Verification::Verified),
+                Err(e) => reason = e,
+            }
+        }
+        Ok(Verification::Invalid(reason))
+    }
+
+    /// Checks one signature layer: the blob matches its digest, names the
+    /// image digest, and is signed by one of the keys.
+    fn check_layer(
+        &self,
+        image_digest: &str,
+        layer_digest: &str,
+        payload: &[u8],
+        signature: &str,
+    ) -> Result<(), String> {
+        let actual = format!("sha256:{}", hex(digest(&SHA256, payload).as_ref()));
+        if actual != layer_digest {
+            return Err(format!("signature payload does not match {layer_digest}"));
+        }
+
+        let signed: Value =
+            serde_json::from_slice(payload).map_err(|e| format!("invalid signature payload: {e}"))?;
+        let signed_digest = signed["critical"]["image"]["docker-manifest-digest"].as_str();
+        if signed_digest != Some(image_digest) {
+            return Err(format!(
+                "signature is for {}, not {image_digest}",
+                signed_digest.unwrap_or("<none>")
+            ));
+        }
+
+        let signature = BASE64
+            .decode(signature)
+            .map_err(|e| format!("signature is not base64: {e}"))?;
+        let verified = self.keys.iter().any(|key| {
+            UnparsedPublicKey::new(&ECDSA_P256_SHA256_ASN1, key)
+                .verify(payload, &signature)
+                .is_ok()
+        });
+        if verified {
+            Ok(())
+        } else {
+            Err("no configured public key verifies the signature".to_string())
+        }
+    }
+
+    /// GETs `url`, following redirects and answering a bearer challenge with
+    /// an anonymous token, which is then reused through `token`. Bodies over
+    /// [`MAX_BODY_BYTES`] are an error.
+    async fn get(
+        &self,
+        url: &str,
+        accept: &str,
+        token: &mut Option<String>,
+    ) -> Result<Bytes, VerifyError> {
+        let mut url = url.to_string();
+        let mut authenticated = false;
+        // Blob storage that a registry redirects to must not see its token.
+        let mut send_token = true;
+        for _ in 0..=MAX_REDIRECTS {
+            let uri: Uri = url.parse().map_err(|e: hyper::http::uri::InvalidUri| {
+                VerifyError::Http {
+                    url: url.clone(),
+                    reason: e.to_string(),
+                }
+            })?;
+            let mut request = Request::get(uri.clone()).header(ACCEPT, accept);
+            if let Some(token) = token.as_ref().filter(|_| send_token) {
+                request = request.header(AUTHORIZATION, format!("Bearer {token}"));
+            }
+            let request = request.body(Empty::new()).expect("request parts are valid");
+
+            let http_error = |reason: String| VerifyError::Http {
+                url: url.clone(),
+                reason,
+            };
+            let response = client()
+                .request(request)
+                .await
+                .map_err(|e| http_error(e.to_string()))?;
+            let status = response.status();
+
+            if status == StatusCode::UNAUTHORIZED && !authenticated {
+                let challenge = response
+                    .headers()
+                    .get(WWW_AUTHENTICATE)
+                    .and_then(|h| h.to_str().ok())
+                    .map(str::to_string);
+                if let Some(challenge) = challenge {
+                    *token = Some(self.anonymous_token(&challenge).await?);
+                    authenticated = true;
+                    continue;
+                }
+            }
+            if status.is_redirection() {
+                if let Some(location) = response.headers().get(LOCATION) {
+                    let location = location.to_str().map_err(|e| http_error(e.to_string()))?;
+                    let next = resolve_location(&uri, location);
+                    let same_host = next
+                        .parse::<Uri>()
+                        .is_ok_and(|next| next.authority() == uri.authority());
+                    send_token &= same_host;
+                    url = next;
+                    continue;
+                }
+            }
+            if !status.is_success() {
+                return Err(VerifyError::Status { url, status });
+            }
+
+            let body = Limited::new(response.into_body(), MAX_BODY_BYTES).collect().await;
+            return body
+                .map(|b| b.to_bytes())
+                .map_err(|e| http_error(format!("{e} (limit is {MAX_BODY_BYTES} bytes)")));
+        }
+        Err(VerifyError::Http {
+            url,
+            reason: "too many redirects".to_string(),
+        })
+    }
+
+    /// Fetches a token for a `Bearer realm="...",service="...",scope="..."`
+    /// challenge without credentials.
+    async fn anonymous_token(&self, challenge: &str) -> Result<String, VerifyError> {
+        let invalid = || VerifyError::Http {
+            url: challenge.to_string(),
+            reason: "unsupported authentication challenge".to_string(),
+        };
+        let params = challenge.strip_prefix("Bearer ").ok_or_else(invalid)?;
+        let mut realm = None;
+        let mut query = Vec::new();
+        for param in params.split(',') {
+            let Some((key, value)) = param.trim().split_once('=') else {
+                continue;
+            };
+            let value = value.trim_matches('"');
+            match key {
+                "realm" => realm = Some(value),
+                "service" | "scope" => query.push(format!("{key}={value}")),
+                _ => {}
+            }
+        }
+        let realm = realm.ok_or_else(invalid)?;
+        let url = if query.is_empty() {
+            realm.to_string()
+        } else {
+            format!("{realm}?{}", query.join("&"))
+        };
+
+        let body = Box::pin(self.get(&url, "application/json", &mut None)).await?;
+        let response: Value = serde_json::from_slice(&body).map_err(|_| invalid())?;
+        response["token"]
+            .as_str()
+            .or_else(|| response["access_token"].as_str())
+            .map(str::to_string)
+            .ok_or_else(invalid)
+    }
+}
+
+/// Reads a PEM `PUBLIC KEY` (as written by `cosign generate-key-pair`) into
+/// the uncompressed point `ring` verifies with. Only ECDSA P-256 is supported.
+pub fn parse_public_key(pem: &str) -> Result<Vec<u8>, KeyError> {
+    let body: String = pem
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.starts_with("-----"))
+        .collect();
+    if !pem.contains("-----BEGIN PUBLIC KEY-----") {
+        return Err(KeyError::Pem("missing BEGIN PUBLIC KEY header".to_string()));
+    }
+    let der = BASE64.decode(body).map_err(|e| KeyError::Pem(e.to_string()))?;
+    match der.strip_prefix(P256_SPKI_PREFIX) {
+        Some(point) if point.len() == 65 => Ok(point.to_vec()),
+        _ => Err(KeyError::UnsupportedKey),
+    }
+}
+
+/// Resolves a `Location` header against the URL that returned it. Registries
+/// often redirect to an absolute path on the same host.
+fn resolve_location(base: &Uri, location: &str) -> String {
+    if location.parse::<Uri>().is_ok_and(|uri| uri.scheme().is_some()) {
+        return location.to_string();
+    }
+    let scheme = base.scheme_str().unwrap_or("https");
+    if let Some(rest) = location.strip_prefix("//") {
+        return format!("{scheme}://{rest}");
+    }
+    let authority = base.authority().map_or("", |a| a.as_str());
+    if location.starts_with('/') {
+        return format!("{scheme}://{authority}{location}");
+    }
+    let dir = base.path().rsplit_once('/').map_or("", |(dir, _)| dir);
+    format!("{scheme}://{authority}{dir}/{location}")
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// A local stand-in for an OCI registry serving cosign signatures, for tests.
+#[cfg(test)]
+pub mod testing {
+    use std::net::SocketAddr;
+
+    use axum::extract::{Path, State};
+    use axum::http::StatusCode;
+    use axum::routing::get;
+    use axum::Router;
+    use ring::rand::SystemRandom;
+    use ring::signature::{EcdsaKeyPair, KeyPair, ECDSA_P256_SHA256_ASN1_SIGNING};
+    use serde_json::json;
+
+    use super::*;
+
+    pub struct SigningKey(EcdsaKeyPair);
+
+    impl SigningKey {
+        pub fn generate() -> Self {
+            let rng = SystemRandom::new();
+            let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, &rng).unwrap();
+            let pair =
+                EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, pkcs8.as_ref(), &rng)
+                    .unwrap();
+            Self(pair)
+        }
+
+        pub fn public_key_pem(&self) -> String {
+            let der = [P256_SPKI_PREFIX, self.0.public_key().as_ref()].concat();
+            format!(
+                "-----BEGIN PUBLIC KEY-----\n{}\n-----END PUBLIC KEY-----\n",
+                BASE64.encode(der)
+            )
+        }
+
+        /// The `(payload, base64 signature)` cosign would push for `digest`.
+        pub fn sign(&self, digest: &str) -> (Vec<u8>, String) {
+            let payload = serde_json::to_vec(&json!({
+                "critical": {
+                    "identity": {"docker-reference": "example"},
+                    "image": {"docker-manifest-digest": digest},
+                    "type": "cosign container image signature",
+                },
+                "optional": null,
+            }))
+            .unwrap();
+            let signature = self.0.sign(&SystemRandom::new(), &payload).unwrap();
+            (payload, BASE64.encode(signature.as_ref()))
+        }
+    }
+
+    /// Serves `/v2/<repo>/manifests/<tag>` and `/v2/<repo>/blobs/<digest>`
+    /// for the given signatures, keyed by image digest.
+    pub async fn serve_registry(signatures: Vec<(String, Vec<u8>, String)>) -> SocketAddr {
+        let mut manifests = HashMap::new();
+        let mut blobs = HashMap::new();
+        for (digest, payload, signature) in signatures {
+            let blob_digest = format!("sha256:{}", hex(ring::digest::digest(&SHA256, &payload).as_ref()));
+            let manifest = json!({
+                "schemaVersion": 2,
+                "layers": [{
+                    "mediaType": "application/vnd.dev.cosign.simplesigning.v1+json",
+                    "digest": blob_digest,
+                    "size": payload.len(),
+                    "annotations": {SIGNATURE_ANNOTATION: signature},
+                }],
+            });
+            manifests.insert(digest.replacen(':', "-", 1) + ".sig", manifest.to_string());
+            blobs.insert(blob_digest, payload);
+        }
+
+        type Served = Arc<(HashMap<String, String>, HashMap<String, Vec<u8>>)>;
+        async fn handler(
+            State(served): State<Served>,
+            Path(path): Path<String>,
+        ) -> Result<Vec<u8>, StatusCode> {
+            let (manifests, blobs) = &*served;
+            let (_, rest) = path.split_once("/manifests/").unwrap_or(("", ""));
+            if let Some(manifest) = manifests.get(rest) {
+                return Ok(manifest.clone().into_bytes());
+            }
+            let (_, rest) = path.split_once("/blobs/").unwrap_or(("", ""));
+            blobs.get(rest).cloned().ok_or(StatusCode::NOT_FOUND)
+        }
+
+        let app = Router::new()
+            .route("/v2/{*path}", get(handler))
+            .with_state(Arc::new((manifests, blobs)));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        addr
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::testing::{serve_registry, SigningKey};
+    use super::*;
+
+    #[tokio::test]
+    async fn test_verify_against_local_registry() {
+        let key = SigningKey::generate();
+        let other = SigningKey::generate();
+        let signed = format!("sha256:{}", "a".repeat(64));
+        let forged = format!("sha256:{}", "b".repeat(64));
+        let unsigned = format!("sha256:{}", "c".repeat(64));
+
+        let (payload, signature) = key.sign(&signed);
+        let (forged_payload, forged_signature) = other.sign(&forged);
+        let addr = serve_registry(vec![
+            (signed.clone(), payload, signature),
+            (forged.clone(), forged_payload, forged_signature),
+        ])
+        .await;
+
+        let host = addr.to_string();
+        let verifier = SignatureVerifier::new(
+            vec![parse_public_key(&key.public_key_pem()).unwrap()],
+            vec![host.clone()],
+            Duration::from_secs(5),
+            Duration::from_secs(60),
+        );
+        let repository = format!("{host}/team/app");
+
+        assert_eq!(verifier.verify(&repository, &signed).await.unwrap(), Verification::Verified);
+        assert_eq!(verifier.verify(&repository, &unsigned).await.unwrap(), Verification::Unsigned);
+        assert!(matches!(
+            verifier.verify(&repository, &forged).await.unwrap(),
+            Verification::Invalid(_)
+        ));
+        let cache = CACHE.lock().unwrap();
+        assert!(cache.contains_key(&verifier.cache_key(&signed)));
+        assert!(!cache.contains_key(&format!("other/{signed}")));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_verify_all_under_one_deadline() {
+        async fn slow() -> &'static str {
+            tokio::time::sleep(Duration::from_secs(5)).await;
+            "{}"
+        }
+        let app = axum::Router::new().route("/v2/{*path}", axum::routing::get(slow));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let host = listener.local_addr().unwrap().to_string();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let verifier = Arc::new(SignatureVerifier::new(
+            vec![parse_public_key(&SigningKey::generate().public_key_pem()).unwrap()],
+            vec![host.clone()],
+            Duration::from_millis(200),
+            Duration::from_secs(60),
+        ));
+        let images = (0..4)
+            .map(|i| (format!("{host}/team/app"), format!("sha256:{}", i.to_string().repeat(64))))
+            .collect();
+
+        let start = Instant::now();
+        let results = verifier.verify_all_blocking(images);
+        assert!(start.elapsed() < Duration::from_secs(1), "{:?}", start.elapsed());
+        assert_eq!(results.len(), 4);
+        assert!(results.iter().all(|r| matches!(r, Err(VerifyError::Timeout(_)))));
+    }
+
+    #[tokio::test]
+    async fn test_redirects_and_body_limit() {
+        use axum::http::header::LOCATION;
+        use axum::routing::get;
+
+        let app = axum::Router::new()
+            .route(
+                "/v2/absolute",
+                get(|| async { (StatusCode::TEMPORARY_REDIRECT, [(LOCATION, "/blobs/a")]) }),
+            )
+            .route("/v2/relative", get(|| async { (StatusCode::FOUND, [(LOCATION, "blobs/a")]) }))
+            .route("/blobs/a", get(|| async { "root" }))
+            .route("/v2/blobs/a", get(|| async { "nested" }))
+            .route("/v2/large", get(|| async { vec![0u8; MAX_BODY_BYTES + 1] }));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let verifier = SignatureVerifier::new(vec![], vec![], Duration::ZERO, Duration::ZERO);
+        let mut bodies = Vec::new();
+        for path in ["/v2/absolute", "/v2/relative", "/v2/large"] {
+            bodies.push(verifier.get(&format!("{base}{path}"), "*/*", &mut None).await);
+        }
+        assert_eq!(bodies[0].as_ref().unwrap(), "root");
+        assert_eq!(bodies[1].as_ref().unwrap(), "nested");
+        assert!(matches!(bodies[2], Err(VerifyError::Http { .. })));
+
+        let uri: Uri = "https://registry.example/v2/app/blobs/x".parse().unwrap();
+        assert_eq!(resolve_location(&uri, "https://cdn.example/x"), "https://cdn.example/x");
+        assert_eq!(resolve_location(&uri, "//cdn.example/x"), "https://cdn.example/x");
+    }
+
+    #[test]
+    fn test_parse_public_key() {
+        let pem = SigningKey::generate().public_key_pem();
+        assert_eq!(parse_public_key(&pem).unwrap().len(), 65);
+        assert!(matches!(parse_public_key("not a key"), Err(KeyError::Pem(_))));
+
+        let rsa = "-----BEGIN PUBLIC KEY-----\nMA0GCSqGSIb3DQEBAQUAA4GN\n-----END PUBLIC KEY-----";
+        assert!(matches!(parse_public_key(rsa), Err(KeyError::UnsupportedKey)));
+    }
+}
@@ -0,0 +1,56 @@
+//! Builders shared by unit tests.
+
+use kube::core::admission::{AdmissionRequest, AdmissionReview};
+use kube::core::DynamicObject;
+use serde_json::{json, Value};
+
+use crate::config::default_pod_templates;
+use crate::policies::PodTemplates;
+
+/// An admission request for `object`, defaulting to a CREATE by `alice` in
+/// `default`. Top-level request fields in `fields` replace the defaults.
+pub fn request(object: Value, fields: Value) -> AdmissionRequest<DynamicObject> {
+    let api_version = object["apiVersion"].as_str().unwrap_or("v1");
+    let (group, version) = api_version.split_once('/').unwrap_or(("", api_version));
+    let kind = object["kind"].as_str().unwrap_or("Pod");
+    let name = object["metadata"]["name"].as_str().unwrap_or("web");
+
+    let mut request = json!({
+        "uid": "test",
+        "kind": {"group": group, "version": version, "kind": kind},
+        "resource": {"group": group, "version": version, "resource": format!("{}s", kind.to_lowercase())},
+        "name": name,
+        "namespace": "default",
+        "operation": "CREATE",
+        "userInfo": {"username": "alice", "groups": ["dev"]},
+        "object": object,
+    });
+    if let Some(fields) = fields.as_object() {
+        for (key, value) in fields {
+            request[key] = value.clone();
+        }
+    }
+
+    let review: AdmissionReview<DynamicObject> = serde_json::from_value(json!({
+        "apiVersion": "admission.k8s.io/v1",
+        "kind": "AdmissionReview",
+        "request": request,
+    }))
+    .unwrap();
+    review.try_into().unwrap()
+}
+
+/// A Pod with the given labels and containers.
+pub fn pod(labels: Value, containers: Value) -> Value {
+    json!({
+        "apiVersion": "v1",
+        "kind": "Pod",
+        "metadata": {"name": "web", "labels": labels},
+        "spec": {"containers": containers},
+    })
+}
+
+/// The built-in pod template kinds.
+pub fn templates() -> PodTemplates {
+    PodTemplates::new(&default_pod_templates()).unwrap()
+}